@@ -717,6 +717,13 @@ impl StacksChainState {
     }
 
     pub fn open_and_exec<F>(mainnet: bool, chain_id: u32, path_str: &str, in_boot_block: F) -> Result<StacksChainState, Error> 
+    where F: FnOnce(&mut ClarityTx) -> () {
+        StacksChainState::open_and_exec_with_integrity_check(mainnet, chain_id, path_str, false, in_boot_block)
+    }
+
+    /// Open the chainstate, optionally checking the Clarity side store's integrity first.  The
+    /// check reads the whole side store, so it's off unless the caller asks for it.
+    pub fn open_and_exec_with_integrity_check<F>(mainnet: bool, chain_id: u32, path_str: &str, integrity_check: bool, in_boot_block: F) -> Result<StacksChainState, Error> 
     where F: FnOnce(&mut ClarityTx) -> () {
         let mut path = PathBuf::from(path_str);
 
@@ -772,7 +779,8 @@ impl StacksChainState {
 
         let headers_state_index = StacksChainState::open_index(&header_index_root, None)?;
 
-        let vm_state = MarfedKV::open(&clarity_state_index_root, Some(&StacksBlockHeader::make_index_block_hash(&MINER_BLOCK_BURN_HEADER_HASH, &MINER_BLOCK_HEADER_HASH)))
+        // if asked, check the side store once at startup, so corruption surfaces here instead of mid-sync
+        let vm_state = MarfedKV::open_with_integrity_check(&clarity_state_index_root, Some(&StacksBlockHeader::make_index_block_hash(&MINER_BLOCK_BURN_HEADER_HASH, &MINER_BLOCK_HEADER_HASH)), integrity_check)
             .map_err(|e| Error::ClarityError(e.into()))?;
 
         let clarity_state = ClarityInstance::new(vm_state);
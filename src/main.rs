@@ -203,7 +203,8 @@ fn main() {
             node_config: vec![testnet::NodeConfig {
                 name: "L1".to_string(),
                 path: format!("/tmp/{}/L1", testnet_id),
-                mem_pool_path: format!("/tmp/{}/L1/mempool", testnet_id)
+                mem_pool_path: format!("/tmp/{}/L1/mempool", testnet_id),
+                check_integrity_on_open: false
             }]
         };
         
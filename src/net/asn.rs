@@ -41,10 +41,11 @@ pub struct ASEntry4 {
 impl ASEntry4 {
     pub fn from_file(asn_file: &String) -> Result<Vec<ASEntry4>, net_error> {
         // each row in asn_file must be one of the following:
-        // ^[:whitespace:]*([0-9]+.[0-9]+.[0-9]+.[0-9]+)/([0-9]+)[:whitespace:]+([0-9]+)[:whitespace:]*$
+        // ^[:whitespace:]*([0-9]+.[0-9]+.[0-9]+.[0-9]+)/([0-9]+)[:whitespace:]+([0-9]+)([:whitespace:]+([0-9]+))?[:whitespace:]*$
         // group 1 is the IP prefix
         // group 2 is the prefix length
         // group 3 is the AS number
+        // group 5 is the (optional) organization ID.  0 or absent means unknown.
         let file_handle = File::open(asn_file)
             .map_err(|_e| net_error::FilesystemError)?;
 
@@ -56,7 +57,7 @@ impl ASEntry4 {
     fn read_asn4_sequence<R: BufRead>(fd: &mut R) -> Result<Vec<ASEntry4>, net_error> {
         let mut asn4 = vec![];
 
-        let asn4_regex = Regex::new("^[ \t]*([0-9]+\\.[0-9]+\\.[0-9]+\\.[0-9]+)/([0-9]+)[ \t]+([0-9]+)[ \t]*$").unwrap();
+        let asn4_regex = Regex::new("^[ \t]*([0-9]+\\.[0-9]+\\.[0-9]+\\.[0-9]+)/([0-9]+)[ \t]+([0-9]+)([ \t]+([0-9]+))?[ \t]*$").unwrap();
         let asn4_whitespace_regex = Regex::new("^[ \t]*$|^[ \t]*#.+$").unwrap();
         let mut line_count = 0;
         let mut parsed = true;
@@ -187,11 +188,23 @@ impl ASEntry4 {
         }
         let asn = asn_opt.unwrap();
 
+        let org = match caps.get(5) {
+            Some(org_match) => {
+                let org_opt = org_match.as_str().parse::<u32>();
+                if org_opt.is_err() {
+                    debug!("Failed to parse org \"{}\"", org_match.as_str());
+                    return Err(net_error::DeserializeError("Failed to parse org".to_string()));
+                }
+                org_opt.unwrap()
+            },
+            None => 0
+        };
+
         Ok(Some(ASEntry4 {
             prefix: prefix,
             mask: mask,
             asn: asn,
-            org: 0      // TODO
+            org: org
         }))
     }
 }
@@ -245,6 +258,30 @@ mod test {
                     },
                 ]),
             },
+            // with and without orgs
+            asn_fixture {
+                text: "1.0.0.0/8 1 1000\n2.1.0.0/16 2\n3.1.0.0/16 3 \t 0\n".to_string(),
+                result: Ok(vec![
+                    ASEntry4 {
+                        prefix: 0x01000000,
+                        mask: 8,
+                        asn: 1,
+                        org: 1000,
+                    },
+                    ASEntry4 {
+                        prefix: 0x02010000,
+                        mask: 16,
+                        asn: 2,
+                        org: 0
+                    },
+                    ASEntry4 {
+                        prefix: 0x03010000,
+                        mask: 16,
+                        asn: 3,
+                        org: 0
+                    },
+                ])
+            },
             // invalid line
             asn_fixture {
                 text: "1.2.3.4.5/24 100".to_string(),
@@ -265,6 +302,11 @@ mod test {
                 text: "1.2.3.0/24 4294967296".to_string(),
                 result: Err(net_error::DeserializeError("Failed to parse ASN4 sequence on line 1".to_string())),
             },
+            // invalid org
+            asn_fixture {
+                text: "1.2.3.0/24 100 4294967296".to_string(),
+                result: Err(net_error::DeserializeError("Failed to parse ASN4 sequence on line 1".to_string())),
+            },
         ];
 
         for test in &tests {
@@ -80,6 +80,8 @@ impl Default for NeighborHealthPoint {
 
 pub const NUM_HEALTH_POINTS : usize = 32;
 pub const HEALTH_POINT_LIFETIME : u64 = 12 * 3600;  // 12 hours
+
+pub const RTT_EWMA_ALPHA : f64 = 0.125;     // weight of each new round-trip time sample
     
 #[derive(Debug, Clone)]
 pub struct NeighborStats {
@@ -99,6 +101,11 @@ pub struct NeighborStats {
     pub peer_resets: u64,
     pub last_reset_time: u64,
     pub msg_rx_counts: HashMap<StacksMessageID, u64>,
+    pub num_reconnects: u64,            // loaded from the PeerDB -- how often we've disconnected from this peer before
+    pub last_disconnect_time: u64,
+    pub peer_version: u32,              // protocol version the peer reported in its last handshake (0 if unknown)
+    pub rtt_ewma_ms: Option<f64>,       // moving average of request/reply round-trip times (None until we get a reply)
+    pub peer_services: u16,             // services bitfield the peer advertised in its last handshake (0 if unknown)
 }
 
 impl NeighborStats {
@@ -119,7 +126,12 @@ impl NeighborStats {
             healthpoints: VecDeque::new(),
             peer_resets: 0,
             last_reset_time: 0,
-            msg_rx_counts: HashMap::new()
+            msg_rx_counts: HashMap::new(),
+            num_reconnects: 0,
+            last_disconnect_time: 0,
+            peer_version: 0,
+            rtt_ewma_ms: None,
+            peer_services: 0,
         }
     }
     
@@ -134,6 +146,15 @@ impl NeighborStats {
         }
     }
 
+    /// Fold a request/reply round-trip time into the peer's moving average.
+    pub fn add_rtt_sample(&mut self, rtt_ms: u64) -> () {
+        let sample = rtt_ms as f64;
+        self.rtt_ewma_ms = match self.rtt_ewma_ms {
+            Some(rtt) => Some(RTT_EWMA_ALPHA * sample + (1.0 - RTT_EWMA_ALPHA) * rtt),
+            None => Some(sample)
+        };
+    }
+
     /// Get a peer's perceived health -- the last $NUM_HEALTH_POINTS successful messages divided by
     /// the total.
     pub fn get_health_score(&self) -> f64 {
@@ -154,6 +175,31 @@ impl NeighborStats {
         }
         (successful as f64) / (total as f64)
     }
+
+    /// Get a peer's health without penalizing stale data points -- the last $NUM_HEALTH_POINTS
+    /// successful messages divided by the total, regardless of when they happened.
+    pub fn get_raw_health_score(&self) -> f64 {
+        // if we don't have enough data, assume 50%
+        if self.healthpoints.len() < NUM_HEALTH_POINTS {
+            return 0.5;
+        }
+
+        let successful = self.healthpoints.iter().filter(|hp| hp.success).count();
+        (successful as f64) / (self.healthpoints.len() as f64)
+    }
+
+    /// How much useful data has this peer relayed to us?  This is the number of blocks,
+    /// microblocks, and transactions messages we've received from it.
+    pub fn get_relay_value(&self) -> u64 {
+        let mut relay_value = 0;
+        for msg_id in [StacksMessageID::Blocks, StacksMessageID::Microblocks, StacksMessageID::Transaction].iter() {
+            relay_value += match self.msg_rx_counts.get(msg_id) {
+                Some(count) => *count,
+                None => 0
+            };
+        }
+        relay_value
+    }
 }
 
 /// P2P ongoing conversation with another Stacks peer
@@ -232,13 +278,19 @@ impl Neighbor {
             None => 0
         };
 
+        let org_opt = PeerDB::org_lookup(conn, &handshake_data.addrbytes)
+            .map_err(net_error::DBError)?;
+
         self.public_key = pubk;
         self.expire_block = handshake_data.expire_block_height;
         self.last_contact_time = get_epoch_time_secs();
 
         if asn != 0 {
             self.asn = asn;
-            self.org = asn;       // TODO; AS number is a place-holder for an organization ID (an organization can own multiple ASs)
+            self.org = match org_opt {
+                Some(org) => org,
+                None => asn       // AS number is a place-holder for an organization ID (an organization can own multiple ASs)
+            };
         }
 
         Ok(())
@@ -537,7 +589,9 @@ impl ConversationP2P {
 
         self.peer_version = preamble.peer_version;
         self.peer_network_id = preamble.network_id;
+        self.stats.peer_version = preamble.peer_version;
         self.peer_services = handshake_data.services;
+        self.stats.peer_services = handshake_data.services;
         self.peer_expire_block_height = handshake_data.expire_block_height;
         self.data_url = handshake_data.data_url.clone();
 
@@ -929,6 +983,10 @@ impl ConversationP2P {
                 self.stats.last_recv_time = now;
                 self.stats.last_contact_time = get_epoch_time_secs();
                 self.stats.add_healthpoint(true);
+
+                if let Some(rtt_ms) = self.connection.request_rtt_ms(&msg) {
+                    self.stats.add_rtt_sample(rtt_ms);
+                }
             }
             else {
                 // got an unauthenticated message we didn't ask for
@@ -1495,6 +1553,10 @@ mod test {
         assert_eq!(unhandled_1.len(), 0);
         assert_eq!(unhandled_2.len(), 1);   // only the handshake is given back.  the ping is consumed
 
+        // convo 1 timed its requests; convo 2 didn't send any
+        assert!(convo_1.stats.rtt_ewma_ms.is_some());
+        assert!(convo_2.stats.rtt_ewma_ms.is_none());
+
         // convo 2 returns the handshake from convo 1
         match unhandled_2[0].payload {
             StacksMessageType::Handshake(ref data) => {
@@ -44,6 +44,7 @@ use net::HttpRequestPreamble;
 use net::HttpResponsePreamble;
 use net::RelayData;
 use net::PeerAddress;
+use net::NeighborMatcher;
 use net::ProtocolFamily;
 use net::StacksP2P;
 use net::StacksHttp;
@@ -58,6 +59,7 @@ use chainstate::burn::ConsensusHash;
 use util::log;
 use util::secp256k1::Secp256k1PublicKey;
 use util::get_epoch_time_secs;
+use util::get_epoch_time_ms;
 use util::sleep_ms;
 use util::hash::to_hex;
 use util::pipe::*;
@@ -69,7 +71,8 @@ use util::pipe::*;
 struct ReceiverNotify<P: ProtocolFamily> {
     expected_seq: u32,
     receiver_input: SyncSender<P::Message>,
-    ttl: u64        // absolute deadline by which this message needs a reply (in seconds since the epoch)
+    ttl: u64,           // absolute deadline by which this message needs a reply (in seconds since the epoch)
+    request_time_ms: u128   // when the request was queued (in milliseconds since the epoch)
 }
 
 impl<P: ProtocolFamily> ReceiverNotify<P> {
@@ -77,7 +80,8 @@ impl<P: ProtocolFamily> ReceiverNotify<P> {
         ReceiverNotify {
             expected_seq: seq,
             receiver_input: input,
-            ttl: ttl
+            ttl: ttl,
+            request_time_ms: get_epoch_time_ms()
         }
     }
 
@@ -285,6 +289,78 @@ struct ConnectionOutbox<P: ProtocolFamily> {
     inflight: VecDeque<ReceiverNotify<P>>
 }
 
+/// Tunables for pruning our frontier.  Fields missing from a serialized config take their default
+/// values.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PruneConfig {
+    pub soft_num_neighbors: u64,
+    pub soft_num_clients: u64,
+    pub soft_max_neighbors_per_org: u64,
+    pub soft_max_clients_per_host: u64,
+    pub count_decay_interval: u64,
+    pub prefer_newer_versions: bool,
+    pub uptime_log_base: f64,
+    pub reserved_outbound_slots: u64,
+    pub min_age_before_prune_secs: u64,
+    pub latency_tier_ms: u64,
+    pub rebalance_interval: u64,
+    pub inbound_shed_threshold: u64,
+    pub inbound_shed_window: u64,
+    pub protected_services: u16,
+    pub prefer_useful_inbound: bool,
+    pub max_prune_per_cycle: u64,
+    pub prune_cooldown_secs: u64,
+    pub uptime_weight: f64,
+    pub health_weight: f64,
+    pub relay_weight: f64,
+    pub latency_weight: f64,
+}
+
+impl std::default::Default for PruneConfig {
+    fn default() -> PruneConfig {
+        PruneConfig {
+            soft_num_neighbors: 20,         // how many outbound connections we can have, before we start pruning them
+            soft_num_clients: 128,          // how many inbound connections we can have, before we start pruning them
+            soft_max_neighbors_per_org: 10,      // how many outbound connections we can have per AS-owning organization, before we start pruning them
+            soft_max_clients_per_host: 10,       // how many inbound connections we can have per IP address, before we start pruning them,
+            count_decay_interval: 86400,    // halve a peer's prune counts if it hasn't been pruned in this long (0 disables decay)
+            prefer_newer_versions: false,   // when pruning outbound peers, prune ones with older protocol versions first
+            uptime_log_base: 2.0,           // bucket uptimes by powers of this when pruning (smaller means finer buckets)
+            reserved_outbound_slots: 0,     // how many of the soft_num_clients + soft_num_neighbors slots inbound peers can never take (0 disables)
+            min_age_before_prune_secs: 0,   // don't prune outbound peers by org until we've known them this long, unless we're over num_neighbors (0 disables)
+            latency_tier_ms: 0,             // when pruning outbound peers, prune ones in slower round-trip time tiers of this width first (0 disables)
+            rebalance_interval: 0,          // at most how often to swap out a marginal outbound peer when we're at soft_num_neighbors (0 disables)
+            inbound_shed_threshold: 0,      // start refusing some new inbound connections once we've pruned more than this many inbound peers... (0 disables)
+            inbound_shed_window: 600,       // ...within this many seconds
+            protected_services: 0,          // prune peers advertising any of these ServiceFlags bits last, within their org or IP (0 disables)
+            prefer_useful_inbound: false,   // when pruning inbound peers by IP, keep ones that relayed us more blocks and transactions over older ones
+            max_prune_per_cycle: 0,         // prune at most this many peers per prune pass, unless we're over num_neighbors (0 disables)
+            prune_cooldown_secs: 300,       // don't select a pruned outbound peer again for this many seconds
+            uptime_weight: 1.0,             // weights of the terms of a neighbor's prune score (see PeerNetwork::prune_score).
+            health_weight: 0.0,             // the defaults score neighbors by uptime bucket alone, leaving health
+            relay_weight: 0.0,              // to break ties after protocol version and churn
+            latency_weight: 0.0,
+        }
+    }
+}
+
+impl PruneConfig {
+    /// Check that the prune tunables make sense
+    pub fn validate(&self) -> Result<(), net_error> {
+        if !(self.uptime_log_base > 1.0) {
+            return Err(net_error::InvalidConnectionOptions(format!("prune uptime_log_base must be greater than 1.0 (got {})", self.uptime_log_base)));
+        }
+        for &(name, weight) in [("uptime_weight", self.uptime_weight), ("health_weight", self.health_weight),
+                                ("relay_weight", self.relay_weight), ("latency_weight", self.latency_weight)].iter() {
+            if !(weight >= 0.0 && weight.is_finite()) {
+                return Err(net_error::InvalidConnectionOptions(format!("prune {} must be finite and non-negative (got {})", name, weight)));
+            }
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct ConnectionOptions {
     pub inbox_maxlen: usize,
@@ -294,14 +370,14 @@ pub struct ConnectionOptions {
     pub private_key_lifetime: u64,
     pub num_neighbors: u64,
     pub num_clients: u64,
-    pub soft_num_neighbors: u64,
-    pub soft_num_clients: u64,
     pub max_neighbors_per_host: u64,
     pub max_clients_per_host: u64,
     pub soft_max_neighbors_per_host: u64,
-    pub soft_max_neighbors_per_org: u64,
-    pub soft_max_clients_per_host: u64,
     pub walk_interval: u64,
+    pub prune: PruneConfig,
+    pub allowlist: Vec<NeighborMatcher>,
+    pub denylist: Vec<NeighborMatcher>,
+    pub org_map_path: Option<String>,
 }
 
 impl std::default::Default for ConnectionOptions {
@@ -314,18 +390,25 @@ impl std::default::Default for ConnectionOptions {
             private_key_lifetime: 4302,     // key expires after ~1 month
             num_neighbors: 32,              // how many outbound connections we can have, full-stop
             num_clients: 256,               // how many inbound connections we can have, full-stop
-            soft_num_neighbors: 20,         // how many outbound connections we can have, before we start pruning them
-            soft_num_clients: 128,          // how many inbound connections we can have, before we start pruning them
             max_neighbors_per_host: 10,     // how many outbound connections we can have per IP address, full-stop
             max_clients_per_host: 10,       // how many inbound connections we can have per IP address, full-stop
             soft_max_neighbors_per_host: 10,     // how many outbound connections we can have per IP address, before we start pruning them
-            soft_max_neighbors_per_org: 10,      // how many outbound connections we can have per AS-owning organization, before we start pruning them
-            soft_max_clients_per_host: 10,       // how many inbound connections we can have per IP address, before we start pruning them,
             walk_interval: 300,             // how often to do a neighbor walk
+            prune: PruneConfig::default(),  // when and how to prune our frontier
+            allowlist: vec![],              // peers we never prune, and (if matched exactly) always reconnect to (unlike seed peers, which are only for bootstrapping)
+            denylist: vec![],               // peers we drop on sight and refuse to talk to
+            org_map_path: None,             // IPv4 prefix to ASN/org map file to load into the peer DB on startup, so we can tell new peers' orgs
         }
     }
 }
 
+impl ConnectionOptions {
+    /// Check that the options make sense together
+    pub fn validate(&self) -> Result<(), net_error> {
+        self.prune.validate()
+    }
+}
+
 #[derive(Debug)]
 pub struct NetworkConnection<P: ProtocolFamily> {
     pub options: ConnectionOptions,
@@ -953,6 +1036,20 @@ impl<P: ProtocolFamily + Clone> NetworkConnection<P> {
         solicited
     }
 
+    /// How many milliseconds passed between queuing the request this message replies to, and now?
+    /// Returns None if the message was unsolicited.
+    pub fn request_rtt_ms(&self, msg: &P::Message) -> Option<u64> {
+        let now = get_epoch_time_ms();
+        for i in 0..self.outbox.inflight.len() {
+            let inflight = self.outbox.inflight.get(i).unwrap();
+            if inflight.expected_seq == msg.request_id() {
+                let rtt = if now > inflight.request_time_ms { now - inflight.request_time_ms } else { 0 };
+                return Some(rtt as u64);
+            }
+        }
+        None
+    }
+
     /// Fulfill an outstanding request with a message.
     /// Return the message itself if the message was unsolicited
     pub fn fulfill_request(&mut self, msg: P::Message) -> Option<P::Message> {
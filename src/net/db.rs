@@ -25,6 +25,9 @@ use rusqlite::Row;
 use rusqlite::Transaction;
 
 use std::fs;
+use std::io::BufReader;
+
+use serde_json;
 use std::convert::From;
 use std::convert::TryFrom;
 
@@ -49,6 +52,7 @@ use net::PeerAddress;
 use net::Neighbor;
 use net::NeighborKey;
 use net::ServiceFlags;
+use net::Error as net_error;
 
 use burnchains::PublicKey;
 use burnchains::PrivateKey;
@@ -57,7 +61,7 @@ use core::NETWORK_P2P_PORT;
 
 use util::strings::UrlString;
 
-pub const PEERDB_VERSION : &'static str = "21.0.0.0";
+pub const PEERDB_VERSION : &'static str = "23.0.0.0";
 
 const NUM_SLOTS : usize = 8;
 
@@ -196,6 +200,35 @@ impl FromRow<ASEntry4> for ASEntry4 {
     }
 }
 
+/// Historic connection churn for a peer, which outlives any one conversation with it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeerChurn {
+    pub num_reconnects: u64,
+    pub last_disconnect_time: u64,
+}
+
+impl FromRow<PeerChurn> for PeerChurn {
+    fn from_row<'a>(row: &'a Row) -> Result<PeerChurn, db_error> {
+        let num_reconnects_i64 : i64 = row.get("num_reconnects");
+        let last_disconnect_time_i64 : i64 = row.get("last_disconnect_time");
+
+        if num_reconnects_i64 < 0 {
+            error!("Invalid num_reconnects {}", num_reconnects_i64);
+            return Err(db_error::ParseError);
+        }
+
+        if last_disconnect_time_i64 < 0 {
+            error!("Invalid last disconnect time {}", last_disconnect_time_i64);
+            return Err(db_error::ParseError);
+        }
+
+        Ok(PeerChurn {
+            num_reconnects: num_reconnects_i64 as u64,
+            last_disconnect_time: last_disconnect_time_i64 as u64,
+        })
+    }
+}
+
 impl FromRow<Neighbor> for Neighbor {
     fn from_row<'a>(row: &'a Row) -> Result<Neighbor, db_error> {
         let peer_version : u32 = row.get("peer_version");
@@ -255,6 +288,20 @@ impl FromRow<Neighbor> for Neighbor {
     }
 }
 
+// added in 22.0.0.0
+const PEERDB_PEER_CHURN_SETUP : &'static str = r#"
+    CREATE TABLE IF NOT EXISTS peer_churn(
+        network_id INTEGER NOT NULL,
+        addrbytes TEXT NOT NULL,
+        port INTEGER NOT NULL,
+
+        -- number of times the peer disconnected from us (so, any later connection is a reconnect)
+        num_reconnects INTEGER NOT NULL,
+        last_disconnect_time INTEGER NOT NULL,
+
+        PRIMARY KEY(network_id,addrbytes,port)
+    );"#;
+
 // In what is likely an abuse of Sqlite, the peer database is structured such that the `frontier`
 // table stores peers keyed by a deterministically-chosen random "slot," instead of their IP/port.
 // (i.e. the slot is determined by a cryptographic the hash of the IP/port).  The reason for this
@@ -280,6 +327,10 @@ const PEERDB_SETUP : &'static [&'static str]= &[
         in_degree INTEGER NOT NULL,
         out_degree INTEGER NOT NULL,
 
+        -- don't select this peer for outbound connections until this time (set when we prune it).
+        -- added in 23.0.0.0
+        prune_cooldown_until INTEGER NOT NULL DEFAULT 0,
+
         -- used to deterministically insert and evict
         slot INTEGER UNIQUE NOT NULL,
 
@@ -295,6 +346,7 @@ const PEERDB_SETUP : &'static [&'static str]= &[
 
         PRIMARY KEY(prefix,mask)
     );"#,
+    PEERDB_PEER_CHURN_SETUP,
     r#"
     CREATE TABLE db_version(version TEXT NOT NULL);
     "#,
@@ -312,6 +364,30 @@ const PEERDB_SETUP : &'static [&'static str]= &[
     );"#
 ];
 
+pub const FRONTIER_SNAPSHOT_VERSION : u32 = 1;
+
+/// A frontier peer, as written to a portable frontier snapshot
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FrontierSnapshotEntry {
+    pub peer_version: u32,
+    pub network_id: u32,
+    pub addrbytes: PeerAddress,
+    pub port: u16,
+    pub public_key: String,         // hex-encoded compressed public key
+    pub expire_block: u64,
+    pub last_contact_time: u64,
+    pub asn: u32,
+    pub org: u32,
+    pub health_score: Option<f64>,  // only set if we were connected to the peer when the snapshot was taken
+}
+
+/// Versioned list of frontier peers, used to warm-start a node or debug its connectivity
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FrontierSnapshot {
+    pub version: u32,
+    pub entries: Vec<FrontierSnapshotEntry>,
+}
+
 pub struct PeerDB {
     pub conn: Connection,
     pub readwrite: bool,
@@ -355,6 +431,66 @@ impl PeerDB {
         Ok(())
     }
 
+    /// Get the schema version of a peer DB
+    fn get_schema_version(conn: &DBConn) -> Result<String, db_error> {
+        conn.query_row("SELECT version FROM db_version", NO_PARAMS, |row| row.get(0))
+            .map_err(db_error::SqliteError)
+    }
+
+    /// Is schema version `version` older than `than`?  Versions are dot-separated integers.
+    fn schema_version_older(version: &str, than: &str) -> bool {
+        let parse = |v: &str| -> Vec<u64> { v.split('.').map(|part| part.parse::<u64>().unwrap_or(0)).collect() };
+        parse(version) < parse(than)
+    }
+
+    /// Does a table have the given column?
+    fn has_column(conn: &DBConn, table: &str, column: &str) -> Result<bool, db_error> {
+        let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))
+            .map_err(db_error::SqliteError)?;
+
+        let mut rows = stmt.query(NO_PARAMS)
+            .map_err(db_error::SqliteError)?;
+
+        while let Some(row_res) = rows.next() {
+            let row = row_res.map_err(db_error::SqliteError)?;
+            let name : String = row.get("name");
+            if name == column {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Bring a peer DB created by an older version of this code up to PEERDB_VERSION.  Each
+    /// step can be safely re-run, so an interrupted upgrade just picks up where it left off.
+    fn migrate(&mut self) -> Result<(), db_error> {
+        let version = PeerDB::get_schema_version(self.conn())?;
+        if !PeerDB::schema_version_older(&version, PEERDB_VERSION) {
+            return Ok(());
+        }
+
+        debug!("Migrating peer DB from version {} to {}", &version, PEERDB_VERSION);
+        let tx = self.tx_begin()?;
+
+        if PeerDB::schema_version_older(&version, "22.0.0.0") {
+            tx.execute(PEERDB_PEER_CHURN_SETUP, NO_PARAMS)
+                .map_err(db_error::SqliteError)?;
+        }
+
+        if PeerDB::schema_version_older(&version, "23.0.0.0") && !PeerDB::has_column(&tx, "frontier", "prune_cooldown_until")? {
+            tx.execute("ALTER TABLE frontier ADD COLUMN prune_cooldown_until INTEGER NOT NULL DEFAULT 0", NO_PARAMS)
+                .map_err(db_error::SqliteError)?;
+        }
+
+        tx.execute("UPDATE db_version SET version = ?1", &[&PEERDB_VERSION])
+            .map_err(db_error::SqliteError)?;
+
+        tx.commit()
+            .map_err(db_error::SqliteError)?;
+
+        Ok(())
+    }
+
     /// Open the burn database at the given path.  Open read-only or read/write.
     /// If opened for read/write and it doesn't exist, instantiate it.
     pub fn connect(path: &String, readwrite: bool, network_id: u32, parent_network_id: u32, key_expires: u64, data_url: UrlString, asn4_recs: &Vec<ASEntry4>, initial_neighbors: Option<&Vec<Neighbor>>) -> Result<PeerDB, db_error> {
@@ -399,6 +535,9 @@ impl PeerDB {
                 }
             }
         }
+        else if readwrite {
+            db.migrate()?;
+        }
         Ok(db)
     }
 
@@ -614,6 +753,20 @@ impl PeerDB {
         Ok(())
     }
 
+    /// Keep a peer we just pruned from being selected for an outbound connection again until
+    /// cooldown_until.  Does nothing if the peer is not present.
+    pub fn set_prune_cooldown<'a>(tx: &mut Transaction<'a>, network_id: u32, peer_addr: &PeerAddress, peer_port: u16, cooldown_until: u64) -> Result<(), db_error> {
+        if cooldown_until > ((1 as u64) << 63) - 1 {
+            return Err(db_error::Overflow);
+        }
+
+        tx.execute("UPDATE frontier SET prune_cooldown_until = ?1 WHERE network_id = ?2 AND addrbytes = ?3 AND port = ?4",
+                   &[&(cooldown_until as i64) as &dyn ToSql, &network_id, &peer_addr.to_hex(), &peer_port])
+            .map_err(db_error::SqliteError)?;
+
+        Ok(())
+    }
+
     /// Update an existing peer's entries.  Does nothing if the peer is not present.
     pub fn update_peer<'a>(tx: &mut Transaction<'a>, neighbor: &Neighbor) -> Result<(), db_error> {
         if neighbor.last_contact_time > ((1 as u64) << 63) - 1 {
@@ -661,7 +814,35 @@ impl PeerDB {
         return Ok(false);
     }
 
-    /// Get random neighbors, optionally always including whitelisted neighbors
+    /// Record that we disconnected from a peer at the given time.
+    /// Each disconnect counts as a reconnect the next time we talk to this peer.
+    pub fn record_peer_disconnect<'a>(tx: &mut Transaction<'a>, network_id: u32, peer_addr: &PeerAddress, peer_port: u16, disconnect_time: u64) -> Result<(), db_error> {
+        if disconnect_time > ((1 as u64) << 63) - 1 {
+            return Err(db_error::Overflow);
+        }
+
+        tx.execute("INSERT OR REPLACE INTO peer_churn (network_id, addrbytes, port, num_reconnects, last_disconnect_time) \
+                    VALUES (?1, ?2, ?3, COALESCE((SELECT num_reconnects FROM peer_churn WHERE network_id = ?1 AND addrbytes = ?2 AND port = ?3), 0) + 1, ?4)",
+                   &[&network_id as &dyn ToSql, &peer_addr.to_hex() as &dyn ToSql, &peer_port as &dyn ToSql, &(disconnect_time as i64) as &dyn ToSql])
+            .map_err(db_error::SqliteError)?;
+
+        Ok(())
+    }
+
+    /// Get a peer's historic churn.  Peers we never disconnected from have no churn.
+    pub fn get_peer_churn(conn: &DBConn, network_id: u32, peer_addr: &PeerAddress, peer_port: u16) -> Result<PeerChurn, db_error> {
+        let qry = "SELECT * FROM peer_churn WHERE network_id = ?1 AND addrbytes = ?2 AND port = ?3".to_string();
+        let args = [&network_id as &dyn ToSql, &peer_addr.to_hex() as &dyn ToSql, &peer_port as &dyn ToSql];
+        let rows = query_rows::<PeerChurn, _>(conn, &qry, &args)?;
+
+        match rows.len() {
+            0 => Ok(PeerChurn { num_reconnects: 0, last_disconnect_time: 0 }),
+            _ => Ok(rows[0].clone())
+        }
+    }
+
+    /// Get random neighbors, optionally always including whitelisted neighbors.
+    /// Non-whitelisted peers we recently pruned are skipped until their cooldown passes.
     pub fn get_random_neighbors(conn: &DBConn, network_id: u32, count: u32, block_height: u64, always_include_whitelisted: bool) -> Result<Vec<Neighbor>, db_error> {
         if block_height > ((1 as u64) << 63) - 1 {
             return Err(db_error::Overflow);
@@ -694,11 +875,11 @@ impl PeerDB {
         // fill in with non-whitelisted, randomly-chosen, fresh peers 
         let random_peers_qry = 
             if always_include_whitelisted {
-                "SELECT * FROM frontier WHERE network_id = ?1 AND last_contact_time >= 0 AND ?2 < expire_block_height AND blacklisted < ?3 AND \
+                "SELECT * FROM frontier WHERE network_id = ?1 AND last_contact_time >= 0 AND ?2 < expire_block_height AND blacklisted < ?3 AND prune_cooldown_until <= ?3 AND \
                  (whitelisted >= 0 AND whitelisted <= ?4) ORDER BY RANDOM() LIMIT ?5".to_string()
             }
             else {
-                "SELECT * FROM frontier WHERE network_id = ?1 AND last_contact_time >= 0 AND ?2 < expire_block_height AND blacklisted < ?3 AND \
+                "SELECT * FROM frontier WHERE network_id = ?1 AND last_contact_time >= 0 AND ?2 < expire_block_height AND blacklisted < ?3 AND prune_cooldown_until <= ?3 AND \
                  (whitelisted < 0 OR (whitelisted >= 0 AND whitelisted <= ?4)) ORDER BY RANDOM() LIMIT ?5".to_string()
             };
 
@@ -709,6 +890,55 @@ impl PeerDB {
         Ok(ret)
     }
 
+    /// Get a randomized set of fresh, non-blacklisted peers that belong to any of the given orgs.
+    /// Peers we recently pruned are skipped until their cooldown passes.
+    pub fn get_random_neighbors_in_orgs(conn: &DBConn, network_id: u32, orgs: &Vec<u32>, count: u32, block_height: u64) -> Result<Vec<Neighbor>, db_error> {
+        if block_height > ((1 as u64) << 63) - 1 {
+            return Err(db_error::Overflow);
+        }
+
+        if orgs.len() == 0 {
+            return Ok(vec![]);
+        }
+
+        // UTC time
+        let now_secs = util::get_epoch_time_secs();
+        if now_secs > ((1 as u64) << 63) - 1 {
+            return Err(db_error::Overflow);
+        }
+
+        // org IDs are integers, so they can be safely inlined
+        let org_list = orgs.iter().map(|org| format!("{}", org)).collect::<Vec<String>>().join(",");
+        let qry = format!("SELECT * FROM frontier WHERE network_id = ?1 AND last_contact_time >= 0 AND ?2 < expire_block_height AND blacklisted < ?3 AND prune_cooldown_until <= ?3 AND \
+                           org IN ({}) ORDER BY RANDOM() LIMIT ?4", org_list);
+        let args = [&network_id as &dyn ToSql, &(block_height as i64) as &dyn ToSql, &(now_secs as i64) as &dyn ToSql, &count as &dyn ToSql];
+        let rows = query_rows::<Neighbor, _>(conn, &qry, &args)?;
+        Ok(rows)
+    }
+
+    /// Get the distinct organizations of the peers in the frontier, in ascending order
+    pub fn get_frontier_orgs(conn: &DBConn, network_id: u32) -> Result<Vec<u32>, db_error> {
+        let mut stmt = conn.prepare("SELECT DISTINCT org FROM frontier WHERE network_id = ?1 ORDER BY org ASC")
+            .map_err(db_error::SqliteError)?;
+
+        let mut rows = stmt.query(&[&network_id as &dyn ToSql])
+            .map_err(db_error::SqliteError)?;
+
+        let mut orgs = vec![];
+        while let Some(row_res) = rows.next() {
+            match row_res {
+                Ok(row) => {
+                    let org : u32 = row.get(0);
+                    orgs.push(org);
+                },
+                Err(e) => {
+                    return Err(db_error::SqliteError(e));
+                }
+            }
+        }
+        Ok(orgs)
+    }
+
     /// Get an randomized initial set of peers.
     /// -- always include all whitelisted neighbors
     /// -- never include blacklisted neighbors
@@ -733,9 +963,9 @@ impl PeerDB {
         Ok(())
     }
 
-    /// Classify an IPv4 address to its AS number.
+    /// Find the IPv4 prefix to ASN/org map entry for an IPv4 address.
     /// This method doesn't have to be particularly efficient since it's off the critical path.
-    pub fn asn4_lookup(conn: &DBConn, addrbits: &PeerAddress) -> Result<Option<u32>, db_error> {
+    fn asn4_entry_lookup(conn: &DBConn, addrbits: &PeerAddress) -> Result<Option<ASEntry4>, db_error> {
         // must be an IPv4 address 
         if !addrbits.is_ipv4() {
             return Err(db_error::TypeError);
@@ -749,7 +979,42 @@ impl PeerDB {
         let rows = query_rows::<ASEntry4, _>(conn, &qry.to_string(), &args)?;
         match rows.len() {
             0 => Ok(None),
-            _ => Ok(Some(rows[0].asn))
+            _ => Ok(Some(rows[0].clone()))
+        }
+    }
+
+    /// Classify an IPv4 address to its AS number.
+    pub fn asn4_lookup(conn: &DBConn, addrbits: &PeerAddress) -> Result<Option<u32>, db_error> {
+        let entry_opt = PeerDB::asn4_entry_lookup(conn, addrbits)?;
+        Ok(entry_opt.map(|entry| entry.asn))
+    }
+
+    /// Add or replace IPv4 prefix to ASN/org mappings in an existing DB
+    pub fn load_asn4_entries(&mut self, asn4_entries: &Vec<ASEntry4>) -> Result<(), db_error> {
+        let mut tx = self.tx_begin()?;
+        for asn4 in asn4_entries {
+            PeerDB::asn4_insert(&mut tx, &asn4)?;
+        }
+        tx.commit()
+            .map_err(db_error::SqliteError)?;
+        Ok(())
+    }
+
+    /// Classify an IPv4 address to its organization ID.
+    /// Returns None if no prefix matches, or the matching prefix has no known org.
+    pub fn org4_lookup(conn: &DBConn, addrbits: &PeerAddress) -> Result<Option<u32>, db_error> {
+        let entry_opt = PeerDB::asn4_entry_lookup(conn, addrbits)?;
+        Ok(entry_opt.and_then(|entry| if entry.org != 0 { Some(entry.org) } else { None }))
+    }
+
+    /// Classify an IP address to its organization ID
+    pub fn org_lookup(conn: &DBConn, addrbits: &PeerAddress) -> Result<Option<u32>, db_error> {
+        if addrbits.is_ipv4() {
+            PeerDB::org4_lookup(conn, addrbits)
+        }
+        else {
+            // TODO
+            Ok(None)
         }
     }
 
@@ -783,11 +1048,77 @@ impl PeerDB {
         let rows = query_rows::<Neighbor, _>(conn, &qry, NO_PARAMS)?;
         Ok(rows)
     }
+
+    /// Merge the peers in a frontier snapshot file into this DB.
+    /// Peers we already have are only updated if the snapshot saw them more recently than we did,
+    /// and keep their local whitelist/blacklist status.
+    /// Returns the number of peers inserted or updated.
+    pub fn import_frontier(&mut self, path: &str) -> Result<u64, net_error> {
+        let file = fs::File::open(path).map_err(net_error::ReadError)?;
+        let snapshot : FrontierSnapshot = serde_json::from_reader(BufReader::new(file))
+            .map_err(|e| net_error::DeserializeError(format!("Failed to parse frontier snapshot: {:?}", &e)))?;
+
+        if snapshot.version != FRONTIER_SNAPSHOT_VERSION {
+            return Err(net_error::FrontierSnapshotVersion(snapshot.version, FRONTIER_SNAPSHOT_VERSION));
+        }
+
+        let mut num_imported = 0;
+        let mut tx = self.tx_begin().map_err(net_error::DBError)?;
+        for entry in snapshot.entries.iter() {
+            let public_key = Secp256k1PublicKey::from_hex(&entry.public_key)
+                .map_err(|e| net_error::DeserializeError(format!("Invalid public key in frontier snapshot: {:?}", &e)))?;
+
+            match PeerDB::get_peer(&tx, entry.network_id, &entry.addrbytes, entry.port).map_err(net_error::DBError)? {
+                Some(mut local) => {
+                    if local.last_contact_time >= entry.last_contact_time {
+                        // ours is fresher
+                        continue;
+                    }
+
+                    local.addr.peer_version = entry.peer_version;
+                    local.public_key = public_key;
+                    local.expire_block = entry.expire_block;
+                    local.last_contact_time = entry.last_contact_time;
+                    local.asn = entry.asn;
+                    local.org = entry.org;
+
+                    PeerDB::update_peer(&mut tx, &local).map_err(net_error::DBError)?;
+                    num_imported += 1;
+                },
+                None => {
+                    let neighbor = Neighbor {
+                        addr: NeighborKey {
+                            peer_version: entry.peer_version,
+                            network_id: entry.network_id,
+                            addrbytes: entry.addrbytes.clone(),
+                            port: entry.port,
+                        },
+                        public_key: public_key,
+                        expire_block: entry.expire_block,
+                        last_contact_time: entry.last_contact_time,
+                        whitelisted: 0,
+                        blacklisted: 0,
+                        asn: entry.asn,
+                        org: entry.org,
+                        in_degree: 1,
+                        out_degree: 1
+                    };
+
+                    if PeerDB::try_insert_peer(&mut tx, &neighbor).map_err(net_error::DBError)? {
+                        num_imported += 1;
+                    }
+                }
+            }
+        }
+        tx.commit().map_err(|e| net_error::DBError(db_error::SqliteError(e)))?;
+        Ok(num_imported)
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use std::env;
     use net::Neighbor;
     use net::NeighborKey;
     use net::PeerAddress;
@@ -1040,4 +1371,234 @@ mod test {
         let asn_missing_opt = PeerDB::asn4_lookup(db.conn(), &asn4_missing_addr).unwrap();
         assert_eq!(asn_missing_opt, None);
     }
+
+    #[test]
+    fn org4_load_lookup() {
+        let mut db = PeerDB::connect_memory(0x9abcdef0, 12345, 0, "http://foo.com".into(), &vec![], &vec![]).unwrap();
+
+        let org_addr = PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x01,0x02,0x02,0x04]);
+        let no_org_addr = PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x02,0x03,0x13,0x10]);
+        let unmapped_addr = PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x02,0x04,0x13,0x10]);
+        assert_eq!(PeerDB::org_lookup(db.conn(), &org_addr).unwrap(), None);
+
+        // mappings can be loaded into a DB that already exists
+        db.load_asn4_entries(&vec![
+            ASEntry4 {
+                prefix: 0x01020200,
+                mask: 24,
+                asn: 1,
+                org: 1000,
+            },
+            ASEntry4 {
+                prefix: 0x02030000,
+                mask: 16,
+                asn: 4,
+                org: 0
+            },
+        ]).unwrap();
+
+        assert_eq!(PeerDB::org_lookup(db.conn(), &org_addr).unwrap(), Some(1000));
+        assert_eq!(PeerDB::asn_lookup(db.conn(), &org_addr).unwrap(), Some(1));
+
+        // known ASN, but no known org
+        assert_eq!(PeerDB::org_lookup(db.conn(), &no_org_addr).unwrap(), None);
+        assert_eq!(PeerDB::asn_lookup(db.conn(), &no_org_addr).unwrap(), Some(4));
+
+        assert_eq!(PeerDB::org_lookup(db.conn(), &unmapped_addr).unwrap(), None);
+        assert_eq!(PeerDB::asn_lookup(db.conn(), &unmapped_addr).unwrap(), None);
+    }
+
+    #[test]
+    fn test_peer_churn() {
+        let mut db = PeerDB::connect_memory(0x9abcdef0, 12345, 0, "http://foo.com".into(), &vec![], &vec![]).unwrap();
+        let addrbytes = PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x7f,0x00,0x00,0x01]);
+
+        let churn = PeerDB::get_peer_churn(db.conn(), 0x9abcdef0, &addrbytes, 12345).unwrap();
+        assert_eq!(churn, PeerChurn { num_reconnects: 0, last_disconnect_time: 0 });
+
+        {
+            let mut tx = db.tx_begin().unwrap();
+            PeerDB::record_peer_disconnect(&mut tx, 0x9abcdef0, &addrbytes, 12345, 100).unwrap();
+            PeerDB::record_peer_disconnect(&mut tx, 0x9abcdef0, &addrbytes, 12345, 200).unwrap();
+            tx.commit().unwrap();
+        }
+
+        let churn = PeerDB::get_peer_churn(db.conn(), 0x9abcdef0, &addrbytes, 12345).unwrap();
+        assert_eq!(churn, PeerChurn { num_reconnects: 2, last_disconnect_time: 200 });
+
+        // other ports and networks are unaffected
+        let churn = PeerDB::get_peer_churn(db.conn(), 0x9abcdef0, &addrbytes, 12346).unwrap();
+        assert_eq!(churn.num_reconnects, 0);
+
+        let churn = PeerDB::get_peer_churn(db.conn(), 0x9abcdef1, &addrbytes, 12345).unwrap();
+        assert_eq!(churn.num_reconnects, 0);
+    }
+
+    #[test]
+    fn test_prune_cooldown() {
+        let mut db = PeerDB::connect_memory(0x9abcdef0, 12345, 0, "http://foo.com".into(), &vec![], &vec![]).unwrap();
+        let neighbor = Neighbor {
+            addr: NeighborKey {
+                peer_version: 0x12345678,
+                network_id: 0x9abcdef0,
+                addrbytes: PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x7f,0x00,0x00,0x01]),
+                port: 12345,
+            },
+            public_key: Secp256k1PublicKey::from_hex("02fa66b66f8971a8cd4d20ffded09674e030f0f33883f337f34b95ad4935bac0e3").unwrap(),
+            expire_block: 23456,
+            last_contact_time: 1552509642,
+            whitelisted: 0,
+            blacklisted: 0,
+            asn: 34567,
+            org: 45678,
+            in_degree: 1,
+            out_degree: 1
+        };
+
+        {
+            let mut tx = db.tx_begin().unwrap();
+            PeerDB::try_insert_peer(&mut tx, &neighbor).unwrap();
+            tx.commit().unwrap();
+        }
+
+        assert_eq!(PeerDB::get_random_walk_neighbors(db.conn(), 0x9abcdef0, 10, 23455).unwrap(), vec![neighbor.clone()]);
+        assert_eq!(PeerDB::get_random_neighbors_in_orgs(db.conn(), 0x9abcdef0, &vec![45678], 10, 23455).unwrap(), vec![neighbor.clone()]);
+
+        // in cooldown
+        {
+            let mut tx = db.tx_begin().unwrap();
+            PeerDB::set_prune_cooldown(&mut tx, 0x9abcdef0, &neighbor.addr.addrbytes, neighbor.addr.port, util::get_epoch_time_secs() + 3600).unwrap();
+            tx.commit().unwrap();
+        }
+
+        assert_eq!(PeerDB::get_random_walk_neighbors(db.conn(), 0x9abcdef0, 10, 23455).unwrap(), vec![]);
+        assert_eq!(PeerDB::get_random_neighbors_in_orgs(db.conn(), 0x9abcdef0, &vec![45678], 10, 23455).unwrap(), vec![]);
+
+        // the cooldown doesn't affect anything else about the peer
+        assert_eq!(PeerDB::get_peer(db.conn(), 0x9abcdef0, &neighbor.addr.addrbytes, neighbor.addr.port).unwrap(), Some(neighbor.clone()));
+
+        // cooldown passed
+        {
+            let mut tx = db.tx_begin().unwrap();
+            PeerDB::set_prune_cooldown(&mut tx, 0x9abcdef0, &neighbor.addr.addrbytes, neighbor.addr.port, util::get_epoch_time_secs() - 1).unwrap();
+            tx.commit().unwrap();
+        }
+
+        assert_eq!(PeerDB::get_random_walk_neighbors(db.conn(), 0x9abcdef0, 10, 23455).unwrap(), vec![neighbor.clone()]);
+        assert_eq!(PeerDB::get_random_neighbors_in_orgs(db.conn(), 0x9abcdef0, &vec![45678], 10, 23455).unwrap(), vec![neighbor.clone()]);
+    }
+
+    fn make_old_peerdb(path: &String, version: &str, migrations: &[&str]) -> () {
+        PeerDB::connect(path, true, 0x9abcdef0, 12345, 0, "http://foo.com".into(), &vec![], None).unwrap();
+        let conn = Connection::open(path).unwrap();
+        for sql in migrations.iter() {
+            conn.execute(sql, NO_PARAMS).unwrap();
+        }
+        conn.execute("UPDATE db_version SET version = ?1", &[&version]).unwrap();
+    }
+
+    fn temp_peerdb_path() -> String {
+        let mut path = env::temp_dir();
+        path.push(format!("peerdb-{}.sqlite", to_hex(&thread_rng().gen::<[u8; 16]>())));
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_migrate_peer_churn() {
+        let path = temp_peerdb_path();
+        make_old_peerdb(&path, "21.0.0.0", &["DROP TABLE peer_churn"]);
+
+        let addrbytes = PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x7f,0x00,0x00,0x01]);
+
+        // read-only connections can't migrate
+        {
+            let db = PeerDB::connect(&path, false, 0x9abcdef0, 12345, 0, "http://foo.com".into(), &vec![], None).unwrap();
+            assert_eq!(PeerDB::get_schema_version(db.conn()).unwrap(), "21.0.0.0");
+            assert!(PeerDB::get_peer_churn(db.conn(), 0x9abcdef0, &addrbytes, 12345).is_err());
+        }
+
+        for _ in 0..2 {
+            let mut db = PeerDB::connect(&path, true, 0x9abcdef0, 12345, 0, "http://foo.com".into(), &vec![], None).unwrap();
+            assert_eq!(PeerDB::get_schema_version(db.conn()).unwrap(), PEERDB_VERSION);
+            {
+                let mut tx = db.tx_begin().unwrap();
+                PeerDB::record_peer_disconnect(&mut tx, 0x9abcdef0, &addrbytes, 12345, 100).unwrap();
+                tx.commit().unwrap();
+            }
+        }
+
+        // migrating again kept the data
+        let db = PeerDB::connect(&path, true, 0x9abcdef0, 12345, 0, "http://foo.com".into(), &vec![], None).unwrap();
+        assert_eq!(PeerDB::get_peer_churn(db.conn(), 0x9abcdef0, &addrbytes, 12345).unwrap(), PeerChurn { num_reconnects: 2, last_disconnect_time: 100 });
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_migrate_prune_cooldown() {
+        let path = temp_peerdb_path();
+        make_old_peerdb(&path, "22.0.0.0", &[
+            "DROP TABLE frontier",
+            "CREATE TABLE frontier(peer_version INTEGER NOT NULL, network_id INTEGER NOT NULL, addrbytes TEXT NOT NULL, port INTEGER NOT NULL, public_key TEXT NOT NULL, \
+                expire_block_height INTEGER NOT NULL, last_contact_time INTEGER NOT NULL, asn INTEGER NOT NULL, org INTEGER NOT NULL, whitelisted INTEGER NOT NULL, \
+                blacklisted INTEGER NOT NULL, in_degree INTEGER NOT NULL, out_degree INTEGER NOT NULL, slot INTEGER UNIQUE NOT NULL, PRIMARY KEY(slot))"
+        ]);
+
+        let neighbor = Neighbor {
+            addr: NeighborKey {
+                peer_version: 0x12345678,
+                network_id: 0x9abcdef0,
+                addrbytes: PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x7f,0x00,0x00,0x01]),
+                port: 12345,
+            },
+            public_key: Secp256k1PublicKey::from_hex("02fa66b66f8971a8cd4d20ffded09674e030f0f33883f337f34b95ad4935bac0e3").unwrap(),
+            expire_block: 23456,
+            last_contact_time: 1552509642,
+            whitelisted: 0,
+            blacklisted: 0,
+            asn: 34567,
+            org: 45678,
+            in_degree: 1,
+            out_degree: 1
+        };
+
+        {
+            // a peer stored before the migration
+            let conn = Connection::open(&path).unwrap();
+            assert!(!PeerDB::has_column(&conn, "frontier", "prune_cooldown_until").unwrap());
+            conn.execute("INSERT INTO frontier (peer_version, network_id, addrbytes, port, public_key, expire_block_height, last_contact_time, asn, org, whitelisted, blacklisted, in_degree, out_degree, slot) \
+                          VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+                         &[&neighbor.addr.peer_version as &dyn ToSql, &neighbor.addr.network_id as &dyn ToSql, &neighbor.addr.addrbytes.to_hex() as &dyn ToSql, &neighbor.addr.port,
+                           &to_hex(&neighbor.public_key.to_bytes_compressed()), &(neighbor.expire_block as i64) as &dyn ToSql, &(neighbor.last_contact_time as i64) as &dyn ToSql,
+                           &neighbor.asn, &neighbor.org, &neighbor.whitelisted, &neighbor.blacklisted, &(neighbor.in_degree as i64) as &dyn ToSql, &(neighbor.out_degree as i64) as &dyn ToSql, &0])
+                .unwrap();
+        }
+
+        for _ in 0..2 {
+            let db = PeerDB::connect(&path, true, 0x9abcdef0, 12345, 0, "http://foo.com".into(), &vec![], None).unwrap();
+            assert_eq!(PeerDB::get_schema_version(db.conn()).unwrap(), PEERDB_VERSION);
+            assert!(PeerDB::has_column(db.conn(), "frontier", "prune_cooldown_until").unwrap());
+
+            // existing peers start out of cooldown
+            assert_eq!(PeerDB::get_random_walk_neighbors(db.conn(), 0x9abcdef0, 10, 23455).unwrap(), vec![neighbor.clone()]);
+        }
+
+        let mut db = PeerDB::connect(&path, true, 0x9abcdef0, 12345, 0, "http://foo.com".into(), &vec![], None).unwrap();
+        {
+            let mut tx = db.tx_begin().unwrap();
+            PeerDB::set_prune_cooldown(&mut tx, 0x9abcdef0, &neighbor.addr.addrbytes, neighbor.addr.port, util::get_epoch_time_secs() + 3600).unwrap();
+            tx.commit().unwrap();
+        }
+        assert_eq!(PeerDB::get_random_walk_neighbors(db.conn(), 0x9abcdef0, 10, 23455).unwrap(), vec![]);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_schema_version_older() {
+        assert!(PeerDB::schema_version_older("21.0.0.0", "22.0.0.0"));
+        assert!(PeerDB::schema_version_older("9.0.0.0", "10.0.0.0"));
+        assert!(!PeerDB::schema_version_older("22.0.0.0", "22.0.0.0"));
+        assert!(!PeerDB::schema_version_older("22.0.0.1", "22.0.0.0"));
+    }
 }
@@ -165,6 +165,12 @@ pub enum Error {
     AlreadyConnected,
     /// Message already in progress
     InProgress,
+    /// Frontier snapshot has an unsupported version (found, expected)
+    FrontierSnapshotVersion(u32, u32),
+    /// Peer is on our denylist
+    DeniedPeer,
+    /// Connection options are inconsistent
+    InvalidConnectionOptions(String),
 }
 
 impl fmt::Display for Error {
@@ -208,6 +214,9 @@ impl fmt::Display for Error {
             Error::TooManyPeers => write!(f, "Too many peer connections open"),
             Error::AlreadyConnected => write!(f, "Peer already connected"),
             Error::InProgress => write!(f, "Message already in progress"),
+            Error::FrontierSnapshotVersion(found, expected) => write!(f, "Unsupported frontier snapshot version {} (expected {})", found, expected),
+            Error::DeniedPeer => write!(f, "Peer is denylisted"),
+            Error::InvalidConnectionOptions(ref s) => fmt::Display::fmt(s, f),
         }
     }
 }
@@ -253,6 +262,9 @@ impl error::Error for Error {
             Error::TooManyPeers => None,
             Error::AlreadyConnected => None,
             Error::InProgress => None,
+            Error::FrontierSnapshotVersion(_, _) => None,
+            Error::DeniedPeer => None,
+            Error::InvalidConnectionOptions(ref _s) => None,
         }
     }
 }
@@ -597,6 +609,7 @@ pub struct HandshakeData {
 pub enum ServiceFlags {
     RELAY = 0x01,
     RPC = 0x02,
+    ARCHIVAL = 0x04,        // serves historical data, not just recent data
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -1039,6 +1052,53 @@ impl NeighborKey {
     }
 }
 
+/// Matches a neighbor either by its exact key, or by the subnet its address is in.  This is how
+/// the allowlist and denylist pick out peers.
+/// Subnet prefix lengths are in bits of the 16-byte address, so an IPv4 /24 is a prefix of
+/// 96 + 24 = 120 bits of the IPv4-mapped address.  Prefix lengths over 128 are treated as 128.
+/// A subnet matcher with a network ID only matches neighbors on that network.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NeighborMatcher {
+    Exact(NeighborKey),
+    Subnet {
+        addr: PeerAddress,
+        prefix_len: u8,
+        network_id: Option<u32>
+    }
+}
+
+impl NeighborMatcher {
+    pub fn matches(&self, neighbor_key: &NeighborKey) -> bool {
+        match *self {
+            NeighborMatcher::Exact(ref nk) => nk == neighbor_key,
+            NeighborMatcher::Subnet { ref addr, prefix_len, network_id } => {
+                if let Some(network_id) = network_id {
+                    if network_id != neighbor_key.network_id {
+                        return false;
+                    }
+                }
+
+                let prefix_len = if prefix_len > 128 { 128 } else { prefix_len as usize };
+                let prefix = addr.as_bytes();
+                let addr = neighbor_key.addrbytes.as_bytes();
+
+                let num_bytes = prefix_len / 8;
+                if prefix[0..num_bytes] != addr[0..num_bytes] {
+                    return false;
+                }
+
+                let num_bits = prefix_len % 8;
+                if num_bits == 0 {
+                    return true;
+                }
+
+                let mask = 0xffu8 << (8 - num_bits);
+                (prefix[num_bytes] & mask) == (addr[num_bytes] & mask)
+            }
+        }
+    }
+}
+
 /// Entry in the neighbor set
 #[derive(Debug, Clone, PartialEq)]
 pub struct Neighbor {
@@ -862,6 +862,15 @@ impl NeighborWalk {
         }
     }
 
+    /// Which neighbors do we have an outstanding GetNeighbors request to?
+    pub fn getneighbors_pending(&self) -> HashSet<NeighborKey> {
+        let mut pending : HashSet<NeighborKey> = self.unresolved_getneighbors_neighbors.keys().cloned().collect();
+        if self.getneighbors_request.is_some() {
+            pending.insert(self.cur_neighbor.addr.clone());
+        }
+        pending
+    }
+
     /// Pick a random neighbor from the frontier, excluding an optional given neighbor 
     fn pick_random_neighbor(frontier: &HashMap<NeighborKey, Neighbor>, exclude: Option<&Neighbor>) -> Option<Neighbor> {
         let mut rnd = thread_rng();
@@ -1094,11 +1103,32 @@ impl NeighborWalk {
 }
 
 impl PeerNetwork {
-    /// Get some initial fresh random neighbor(s) to crawl
+    /// Get some initial fresh random neighbor(s) to crawl.
+    /// Up to half of them (rounded up) are drawn from orgs that the last prune found to be
+    /// under-represented; the rest come from the usual random walk neighbor selection.
     pub fn get_random_neighbors(&self, num_neighbors: u64, block_height: u64) -> Result<Vec<Neighbor>, net_error> {
-        let neighbors = PeerDB::get_random_walk_neighbors(&self.peerdb.conn(), self.local_peer.network_id, num_neighbors as u32, block_height)
+        let mut neighbors = vec![];
+        if self.under_represented_orgs.len() > 0 {
+            let num_org_neighbors = (num_neighbors + 1) / 2;
+            let mut org_neighbors = PeerDB::get_random_neighbors_in_orgs(&self.peerdb.conn(), self.local_peer.network_id, &self.under_represented_orgs, num_org_neighbors as u32, block_height)
+                .map_err(net_error::DBError)?;
+
+            neighbors.append(&mut org_neighbors);
+        }
+
+        let walk_neighbors = PeerDB::get_random_walk_neighbors(&self.peerdb.conn(), self.local_peer.network_id, num_neighbors as u32, block_height)
             .map_err(net_error::DBError)?;
 
+        for neighbor in walk_neighbors.into_iter() {
+            if (neighbors.len() as u64) >= num_neighbors {
+                break;
+            }
+            if neighbors.iter().any(|n| n.addr == neighbor.addr) {
+                continue;
+            }
+            neighbors.push(neighbor);
+        }
+
         if neighbors.len() == 0 {
             debug!("{:?}: No neighbors available!  Will not begin neighbor walk", &self.local_peer);
             return Err(net_error::NoSuchNeighbor);
@@ -1512,7 +1542,7 @@ impl PeerNetwork {
         // did something
         self.walk_total_step_count += 1;
 
-        match res {
+        let walk_result_opt = match res {
             Ok(mut walk_opt) => {
                 match walk_opt {
                     Some(ref mut walk_result) => {
@@ -1566,7 +1596,19 @@ impl PeerNetwork {
                 self.walk = None;
                 None
             }
-        }
+        };
+
+        self.update_walking_neighbors();
+        walk_result_opt
+    }
+
+    /// Track which neighbors have an outstanding GetNeighbors request, so we don't prune them
+    /// mid-walk.  Replies that arrived or timed out have already been dropped by the walk.
+    pub fn update_walking_neighbors(&mut self) -> () {
+        self.walking_neighbors = match self.walk {
+            Some(ref walk) => walk.getneighbors_pending(),
+            None => HashSet::new()
+        };
     }
 }
 
@@ -2397,17 +2439,17 @@ mod test {
     fn setup_peer_config(i: usize, port_base: u16, neighbor_count: usize, peer_count: usize) -> TestPeerConfig {
         let mut conf = TestPeerConfig::from_port(port_base + (i as u16));
         conf.connection_opts.num_neighbors = neighbor_count as u64;
-        conf.connection_opts.soft_num_neighbors = neighbor_count as u64;
+        conf.connection_opts.prune.soft_num_neighbors = neighbor_count as u64;
 
         conf.connection_opts.num_clients = 256;
-        conf.connection_opts.soft_num_clients = 128;
+        conf.connection_opts.prune.soft_num_clients = 128;
 
         conf.connection_opts.max_clients_per_host = MAX_NEIGHBORS_DATA_LEN as u64;
-        conf.connection_opts.soft_max_clients_per_host = peer_count as u64;
+        conf.connection_opts.prune.soft_max_clients_per_host = peer_count as u64;
 
         conf.connection_opts.max_neighbors_per_host = MAX_NEIGHBORS_DATA_LEN as u64;
         conf.connection_opts.soft_max_neighbors_per_host = (neighbor_count/2) as u64;
-        conf.connection_opts.soft_max_neighbors_per_org = (neighbor_count/2) as u64;
+        conf.connection_opts.prune.soft_max_neighbors_per_org = (neighbor_count/2) as u64;
 
         conf.connection_opts.walk_interval = 0;
 
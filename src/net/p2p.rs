@@ -23,6 +23,8 @@ use net::Neighbor;
 use net::NeighborKey;
 use net::Error as net_error;
 use net::db::PeerDB;
+use net::db::PeerChurn;
+use net::db::{FrontierSnapshot, FrontierSnapshotEntry, FRONTIER_SNAPSHOT_VERSION};
 use net::asn::ASEntry4;
 
 use net::*;
@@ -62,6 +64,9 @@ use std::sync::mpsc::RecvError;
 use std::sync::mpsc::TryRecvError;
 
 use std::net::SocketAddr;
+use std::fs;
+
+use serde_json;
 
 use std::collections::VecDeque;
 use std::collections::HashMap;
@@ -263,15 +268,48 @@ pub struct PeerNetwork {
     pub walk_count: u64,
     pub walk_total_step_count: u64,
 
+    // neighbors with an outstanding GetNeighbors request from the walk.  We don't prune these
+    // until the request resolves.
+    pub walking_neighbors: HashSet<NeighborKey>,
+
     // re-key state 
     pub rekey_handles: Option<HashMap<usize, ReplyHandleP2P>>,
 
     // prune state
     pub prune_deadline: u64,
+    pub rebalance_deadline: u64,
 
     // how often we pruned a given inbound/outbound peer
     pub prune_outbound_counts: HashMap<NeighborKey, u64>,
-    pub prune_inbound_counts: HashMap<NeighborKey, u64>
+    pub prune_inbound_counts: HashMap<NeighborKey, u64>,
+
+    // when we last pruned (or decayed the prune count of) a given inbound/outbound peer
+    pub prune_outbound_times: HashMap<NeighborKey, u64>,
+    pub prune_inbound_times: HashMap<NeighborKey, u64>,
+
+    // how many outbound peers we pruned from each org, ever
+    pub prune_org_counts: HashMap<u32, u64>,
+
+    // when we pruned inbound peers within the last prune.inbound_shed_window seconds, oldest first
+    pub recent_inbound_prunes: VecDeque<u64>,
+
+    // if set, the outbound org pass samples orgs (and inbound shedding rolls) with an RNG seeded
+    // from this instead of at random, so its decisions can be reproduced.
+    pub prune_rng_seed: Option<u64>,
+
+    // set while we're in initial block download.  Outbound pruning then only enforces the hard
+    // num_neighbors cap, so we don't keep cutting off the peers we're syncing from.
+    pub in_ibd: bool,
+
+    // orgs that had fewer than soft_max_neighbors_per_org outbound neighbors after the last prune.
+    // new outbound neighbors are preferentially drawn from these orgs.
+    pub under_represented_orgs: Vec<u32>,
+
+    // how long the prune passes take
+    pub prune_metrics: PruneMetrics,
+
+    // when we can next try to reconnect to a given allowlisted peer
+    pub allowlist_reconnect_deadlines: HashMap<NeighborKey, u64>,
 }
 
 impl PeerNetwork {
@@ -299,24 +337,47 @@ impl PeerNetwork {
             walk_deadline: 0,
             walk_count: 0,
             walk_total_step_count: 0,
+            walking_neighbors: HashSet::new(),
 
             rekey_handles: None,
 
             prune_deadline: 0,
+            rebalance_deadline: 0,
             prune_outbound_counts : HashMap::new(),
             prune_inbound_counts : HashMap::new(),
+            prune_outbound_times : HashMap::new(),
+            prune_inbound_times : HashMap::new(),
+            prune_org_counts : HashMap::new(),
+            recent_inbound_prunes : VecDeque::new(),
+            prune_rng_seed : None,
+            in_ibd : false,
+
+            under_represented_orgs: vec![],
+
+            prune_metrics: PruneMetrics::default(),
+
+            allowlist_reconnect_deadlines: HashMap::new(),
         }
     }
 
     /// Call this instead of new()
     pub fn init(peerdb_path: &String, network_id: u32, peer_version: u32, burnchain: Burnchain, chain_view: BurnchainView, connection_opts: ConnectionOptions, data_url: UrlString, asn4_path: Option<&String>) -> Result<PeerNetwork, net_error> {
+        connection_opts.validate()?;
+
         let asn4_entries = match asn4_path {
             Some(path) => ASEntry4::from_file(path)?,
             None => vec![]
         };
 
-        let peerdb = PeerDB::connect(peerdb_path, true, network_id, burnchain.network_id, chain_view.burn_block_height + connection_opts.private_key_lifetime, data_url, &asn4_entries, None)
+        let mut peerdb = PeerDB::connect(peerdb_path, true, network_id, burnchain.network_id, chain_view.burn_block_height + connection_opts.private_key_lifetime, data_url, &asn4_entries, None)
             .map_err(net_error::DBError)?;
+
+        // unlike asn4_path, which only seeds a new peer DB, this is reloaded every time
+        if let Some(ref org_map_path) = connection_opts.org_map_path {
+            let org_map_entries = ASEntry4::from_file(org_map_path)?;
+            peerdb.load_asn4_entries(&org_map_entries)
+                .map_err(net_error::DBError)?;
+        }
         
         let local_peer = PeerDB::get_local_peer(peerdb.conn())
             .map_err(net_error::DBError)?;
@@ -630,8 +691,20 @@ impl PeerNetwork {
         self.sockets.len()
     }
 
+    /// Is this neighbor on our denylist?
+    pub fn is_denied(&self, neighbor_key: &NeighborKey) -> bool {
+        self.connection_opts.denylist.iter().any(|matcher| matcher.matches(neighbor_key))
+    }
+
+    /// Is this neighbor on our allowlist?
+    pub fn is_allowed(&self, neighbor_key: &NeighborKey) -> bool {
+        self.connection_opts.allowlist.iter().any(|matcher| matcher.matches(neighbor_key))
+    }
+
     /// Check to see if we can register the given socket
     /// * we can't have registered this neighbor already
+    /// * the neighbor can't be denylisted
+    /// * if this is inbound, we can't be shedding inbound load (unless the neighbor is allowlisted)
     /// * if this is inbound, we can't add more than self.num_clients
     fn can_register_peer(&mut self, neighbor_key: &NeighborKey, outbound: bool) -> Result<(), net_error> {
         if self.is_registered(&neighbor_key) {
@@ -639,6 +712,17 @@ impl PeerNetwork {
             return Err(net_error::AlreadyConnected);
         }
 
+        if self.is_denied(&neighbor_key) {
+            info!("{:?}: Refusing connection to denylisted peer {:?}", &self.local_peer, &neighbor_key);
+            return Err(net_error::DeniedPeer);
+        }
+
+        // if we keep having to prune inbound peers, stop taking on so many
+        if !outbound && !self.is_allowed(&neighbor_key) && self.should_shed_inbound(get_epoch_time_secs()) {
+            info!("{:?}: Shedding inbound connection from {:?}", &self.local_peer, &neighbor_key);
+            return Err(net_error::TooManyPeers);
+        }
+
         // consider rate-limits on in-bound peers
         let num_outbound = PeerNetwork::count_outbound_conversations(&self.peers);
         if !outbound && (self.peers.len() as u64) - num_outbound >= self.connection_opts.num_clients {
@@ -668,7 +752,17 @@ impl PeerNetwork {
 
         self.can_register_peer(&neighbor_key, outbound)?;
 
-        let convo = match self.network {
+        // remember how churny this peer has been in the past.  This only informs pruning, so
+        // don't refuse the peer if we can't look it up.
+        let churn = match PeerDB::get_peer_churn(self.peerdb.conn(), neighbor_key.network_id, &neighbor_key.addrbytes, neighbor_key.port) {
+            Ok(churn) => churn,
+            Err(e) => {
+                warn!("{:?}: Failed to look up churn of {:?}: {:?}", &self.local_peer, &neighbor_key, &e);
+                PeerChurn { num_reconnects: 0, last_disconnect_time: 0 }
+            }
+        };
+
+        let mut convo = match self.network {
             None => {
                 return Err(net_error::NotConnected);
             },
@@ -681,6 +775,9 @@ impl PeerNetwork {
             }
         };
         
+        convo.stats.num_reconnects = churn.num_reconnects;
+        convo.stats.last_disconnect_time = churn.last_disconnect_time;
+
         test_debug!("{:?}: Registered {} as event {} (outbound={})", &self.local_peer, &client_addr, event_id, outbound);
 
         self.sockets.insert(event_id, socket);
@@ -741,15 +838,85 @@ impl PeerNetwork {
         }
     }
 
-    /// Deregister by neighbor key 
-    pub fn deregister_neighbor(&mut self, neighbor_key: &NeighborKey) -> () {
+    /// Deregister a socket/event pair whose connection broke or was closed by the remote peer,
+    /// and count the disconnect toward the peer's churn.  Disconnects we start ourselves (prunes,
+    /// timeouts, replaced neighbors) don't count, since they say nothing about the peer.
+    fn deregister_broken_peer(&mut self, event_id: usize) -> () {
+        let broken : Vec<NeighborKey> = self.events.iter()
+            .filter(|(_, ev_id)| **ev_id == event_id)
+            .map(|(nk, _)| nk.clone())
+            .collect();
+
+        for nk in broken.iter() {
+            match self.record_peer_disconnect(nk) {
+                Ok(_) => {},
+                Err(e) => {
+                    warn!("{:?}: Failed to record disconnect from {:?}: {:?}", &self.local_peer, nk, &e);
+                }
+            }
+        }
+
+        self.deregister_peer(event_id);
+    }
+
+    /// Remember that we disconnected from a neighbor, so we can tell churny peers apart later
+    fn record_peer_disconnect(&mut self, neighbor_key: &NeighborKey) -> Result<(), net_error> {
+        let mut tx = self.peerdb.tx_begin().map_err(net_error::DBError)?;
+        PeerDB::record_peer_disconnect(&mut tx, neighbor_key.network_id, &neighbor_key.addrbytes, neighbor_key.port, get_epoch_time_secs())
+            .map_err(net_error::DBError)?;
+        tx.commit().map_err(|e| net_error::DBError(db_error::SqliteError(e)))?;
+        Ok(())
+    }
+
+    /// Write our PeerDB frontier to a portable snapshot file, which another node can load with
+    /// PeerDB::import_frontier().  Peers we're currently talking to also carry their health score.
+    pub fn export_frontier(&self, path: &str) -> Result<(), net_error> {
+        let peers = PeerDB::get_all_peers(self.peerdb.conn()).map_err(net_error::DBError)?;
+
+        let mut entries = vec![];
+        for peer in peers.into_iter() {
+            let health_score = match self.events.get(&peer.addr) {
+                Some(event_id) => self.peers.get(event_id).map(|convo| convo.stats.get_health_score()),
+                None => None
+            };
+
+            entries.push(FrontierSnapshotEntry {
+                peer_version: peer.addr.peer_version,
+                network_id: peer.addr.network_id,
+                addrbytes: peer.addr.addrbytes,
+                port: peer.addr.port,
+                public_key: to_hex(&peer.public_key.to_bytes_compressed()),
+                expire_block: peer.expire_block,
+                last_contact_time: peer.last_contact_time,
+                asn: peer.asn,
+                org: peer.org,
+                health_score: health_score
+            });
+        }
+
+        let snapshot = FrontierSnapshot {
+            version: FRONTIER_SNAPSHOT_VERSION,
+            entries: entries
+        };
+
+        let file = fs::File::create(path).map_err(net_error::WriteError)?;
+        serde_json::to_writer(file, &snapshot)
+            .map_err(|e| net_error::SerializeError(format!("Failed to write frontier snapshot: {:?}", &e)))?;
+        Ok(())
+    }
+
+    /// Deregister by neighbor key.  Does nothing if the neighbor is already gone (e.g. it closed
+    /// the connection itself).
+    /// Returns true if we were still connected to it.
+    pub fn deregister_neighbor(&mut self, neighbor_key: &NeighborKey) -> bool {
         let event_id = match self.events.get(&neighbor_key) {
             None => {
-                return;
+                return false;
             }
             Some(eid) => *eid
         };
         self.deregister_peer(event_id);
+        true
     }
 
     /// Sign a p2p message to be sent to a particular peer we're having a conversation with
@@ -1038,6 +1205,40 @@ impl PeerNetwork {
         }
     }
 
+    /// Reconnect to any allowlisted peers we're not talking to.  Tries each peer at most once per
+    /// connection timeout.  Only exact allowlist entries name a peer we can connect to.
+    fn reconnect_allowlisted(&mut self) -> () {
+        let now = get_epoch_time_secs();
+        let allowlist : Vec<NeighborKey> = self.connection_opts.allowlist
+            .iter()
+            .filter_map(|matcher| match matcher {
+                NeighborMatcher::Exact(nk) => Some(nk.clone()),
+                NeighborMatcher::Subnet { .. } => None
+            })
+            .collect();
+        for nk in allowlist.iter() {
+            if self.is_registered(nk) {
+                continue;
+            }
+
+            if let Some(deadline) = self.allowlist_reconnect_deadlines.get(nk) {
+                if *deadline > now {
+                    continue;
+                }
+            }
+
+            self.allowlist_reconnect_deadlines.insert(nk.clone(), now + self.connection_opts.timeout);
+            match self.connect_peer(nk) {
+                Ok(event_id) => {
+                    debug!("{:?}: Reconnected to allowlisted peer {:?} as event {}", &self.local_peer, nk, event_id);
+                },
+                Err(e) => {
+                    debug!("{:?}: Failed to reconnect to allowlisted peer {:?}: {:?}", &self.local_peer, nk, &e);
+                }
+            }
+        }
+    }
+
     /// Prune inbound and outbound connections if we can 
     fn prune_connections(&mut self) -> () {
         test_debug!("Prune connections");
@@ -1078,6 +1279,13 @@ impl PeerNetwork {
         };
 
         self.prune_frontier(&safe);
+
+        match self.rebalance_frontier(&safe) {
+            Ok(_) => {},
+            Err(e) => {
+                warn!("{:?}: failed to rebalance frontier: {:?}", &self.local_peer, &e);
+            }
+        }
     }
 
     /// Regenerate our session private key and re-handshake with everyone.
@@ -1223,14 +1431,14 @@ impl PeerNetwork {
         let (error_events, unhandled_messages) = self.process_ready_sockets(&mut poll_state);
         for error_event in error_events {
             debug!("{:?}: Failed connection on event {}", &self.local_peer, error_event);
-            self.deregister_peer(error_event);
+            self.deregister_broken_peer(error_event);
         }
 
         // move conversations along
         let error_events = self.flush_relay_handles();
         for error_event in error_events {
             debug!("{:?}: Failed connection on event {}", &self.local_peer, error_event);
-            self.deregister_peer(error_event);
+            self.deregister_broken_peer(error_event);
         }
 
         // remove timed-out requests from other threads 
@@ -1240,6 +1448,9 @@ impl PeerNetwork {
         
         // clear out peers that we haven't heard from in our heartbeat interval
         self.disconnect_unresponsive();
+
+        // get back any allowlisted peers we lost, before the walk can take their slots
+        self.reconnect_allowlisted();
         
         // walk the peer graph and deal with new/dropped connections
         let mut do_prune = false;
@@ -1257,7 +1468,7 @@ impl PeerNetwork {
         let error_outbound_events = self.send_outbound_messages();
         for error_event in error_outbound_events {
             debug!("{:?}: Failed connection on event {}", &self.local_peer, error_event);
-            self.deregister_peer(error_event);
+            self.deregister_broken_peer(error_event);
         }
         
         if do_prune {
@@ -1317,9 +1528,11 @@ mod test {
     use net::codec::*;
     use std::thread;
     use std::time;
+    use std::env;
     use util::log;
     use burnchains::*;
     use burnchains::burnchain::*;
+    use net::prune::sim::PeerNetworkPruneSim;
 
     use rand::RngCore;
     use rand;
@@ -1386,6 +1599,236 @@ mod test {
         p2p
     }
 
+    #[test]
+    fn test_export_import_frontier() {
+        let mut neighbors = vec![];
+        for i in 0..3 {
+            let mut neighbor = make_test_neighbor(2200 + i);
+            neighbor.org = 100 + (i as u32);
+            neighbors.push(neighbor);
+        }
+
+        let p2p = make_test_p2p_network(&neighbors);
+
+        let mut tmp_path = env::temp_dir();
+        tmp_path.push(format!("test_export_import_frontier_{}.json", rand::random::<u64>()));
+        let path = tmp_path.to_str().unwrap().to_string();
+        p2p.export_frontier(&path).unwrap();
+
+        // round-trip into an empty DB
+        let mut peerdb = PeerDB::connect_memory(0x9abcdef0, 0, 23456, "http://test-p2p.com".into(), &vec![], &vec![]).unwrap();
+        assert_eq!(peerdb.import_frontier(&path).unwrap(), 3);
+
+        let imported = PeerDB::get_all_peers(peerdb.conn()).unwrap();
+        assert_eq!(imported.len(), 3);
+        for neighbor in neighbors.iter() {
+            let peer = PeerDB::get_peer(peerdb.conn(), neighbor.addr.network_id, &neighbor.addr.addrbytes, neighbor.addr.port).unwrap().unwrap();
+            assert_eq!(peer.addr, neighbor.addr);
+            assert_eq!(peer.public_key, neighbor.public_key);
+            assert_eq!(peer.last_contact_time, neighbor.last_contact_time);
+            assert_eq!(peer.org, neighbor.org);
+            assert_eq!(peer.asn, neighbor.asn);
+        }
+
+        // fresher local data is not overwritten
+        let mut fresher = neighbors[0].clone();
+        fresher.last_contact_time += 1;
+        fresher.org = 999;
+        {
+            let mut tx = peerdb.tx_begin().unwrap();
+            PeerDB::update_peer(&mut tx, &fresher).unwrap();
+            tx.commit().unwrap();
+        }
+
+        assert_eq!(peerdb.import_frontier(&path).unwrap(), 0);
+        let peer = PeerDB::get_peer(peerdb.conn(), fresher.addr.network_id, &fresher.addr.addrbytes, fresher.addr.port).unwrap().unwrap();
+        assert_eq!(peer.org, 999);
+
+        // unsupported versions are rejected
+        let mut snapshot : FrontierSnapshot = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        snapshot.version = FRONTIER_SNAPSHOT_VERSION + 1;
+        fs::write(&path, serde_json::to_string(&snapshot).unwrap()).unwrap();
+
+        assert_eq!(peerdb.import_frontier(&path).unwrap_err(), net_error::FrontierSnapshotVersion(FRONTIER_SNAPSHOT_VERSION + 1, FRONTIER_SNAPSHOT_VERSION));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_refuse_denylisted_peers() {
+        let denied = make_test_neighbor(2300);
+        let mut allowed = make_test_neighbor(2301);
+        allowed.addr.addrbytes = PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x7f,0x00,0x01,0x01]);
+
+        let mut p2p = make_test_p2p_network(&vec![]);
+
+        // 127.0.0.0/24
+        p2p.connection_opts.denylist.push(NeighborMatcher::Subnet {
+            addr: PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x7f,0x00,0x00,0x00]),
+            prefix_len: 120,
+            network_id: None
+        });
+
+        match p2p.can_register_peer(&denied.addr, false) {
+            Err(net_error::DeniedPeer) => {},
+            res => panic!("inbound denylisted peer not refused: {:?}", res)
+        }
+        match p2p.can_register_peer(&denied.addr, true) {
+            Err(net_error::DeniedPeer) => {},
+            res => panic!("outbound denylisted peer not refused: {:?}", res)
+        }
+        assert!(p2p.can_register_peer(&allowed.addr, false).is_ok());
+    }
+
+    #[test]
+    fn test_neighbor_matcher_exact() {
+        let nk = make_test_neighbor(2400).addr;
+        let matcher = NeighborMatcher::Exact(nk.clone());
+        assert!(matcher.matches(&nk));
+
+        let mut other_port = nk.clone();
+        other_port.port = 2401;
+        assert!(!matcher.matches(&other_port));
+
+        let mut other_addr = nk.clone();
+        other_addr.addrbytes = PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x7f,0x00,0x00,0x02]);
+        assert!(!matcher.matches(&other_addr));
+
+        let mut other_network = nk.clone();
+        other_network.network_id = 0x9abcdef1;
+        assert!(!matcher.matches(&other_network));
+
+        let mut other_version = nk.clone();
+        other_version.peer_version = 0x12345679;
+        assert!(!matcher.matches(&other_version));
+    }
+
+    #[test]
+    fn test_neighbor_matcher_subnet() {
+        let ipv4 = |a: u8, b: u8, c: u8, d: u8| {
+            let mut nk = make_test_neighbor(2500).addr;
+            nk.addrbytes = PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,a,b,c,d]);
+            nk
+        };
+        let subnet = |a: u8, b: u8, c: u8, d: u8, prefix_len: u8, network_id: Option<u32>| NeighborMatcher::Subnet {
+            addr: PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,a,b,c,d]),
+            prefix_len: prefix_len,
+            network_id: network_id
+        };
+
+        // 127.0.0.0/24, on a byte boundary
+        let matcher = subnet(127, 0, 0, 0, 120, None);
+        assert!(matcher.matches(&ipv4(127, 0, 0, 0)));
+        assert!(matcher.matches(&ipv4(127, 0, 0, 255)));
+        assert!(!matcher.matches(&ipv4(127, 0, 1, 0)));
+        assert!(!matcher.matches(&ipv4(126, 0, 0, 1)));
+
+        // 10.0.2.0/25, mid-byte
+        let matcher = subnet(10, 0, 2, 0, 121, None);
+        assert!(matcher.matches(&ipv4(10, 0, 2, 0x7f)));
+        assert!(!matcher.matches(&ipv4(10, 0, 2, 0x80)));
+
+        // 0.0.0.0/1 -- only the first bit of the IPv4 address counts
+        let matcher = subnet(0, 0, 0, 0, 97, None);
+        assert!(matcher.matches(&ipv4(127, 255, 255, 255)));
+        assert!(!matcher.matches(&ipv4(128, 0, 0, 0)));
+
+        // a zero-length prefix matches everything, IPv6 included
+        let matcher = subnet(1, 2, 3, 4, 0, None);
+        assert!(matcher.matches(&ipv4(5, 6, 7, 8)));
+        let mut ipv6 = ipv4(5, 6, 7, 8);
+        ipv6.addrbytes = PeerAddress([0x20,0x01,0x0d,0xb8,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x01]);
+        assert!(matcher.matches(&ipv6));
+
+        // the ::ffff:0:0/96 prefix matches every IPv4 address, but no IPv6 ones
+        let matcher = subnet(0, 0, 0, 0, 96, None);
+        assert!(matcher.matches(&ipv4(5, 6, 7, 8)));
+        assert!(!matcher.matches(&ipv6));
+
+        // full-length prefixes match a single address on any port, and longer ones are clamped
+        for prefix_len in [128, 200].iter() {
+            let matcher = subnet(127, 0, 0, 1, *prefix_len, None);
+            let mut nk = ipv4(127, 0, 0, 1);
+            assert!(matcher.matches(&nk));
+            nk.port = 2501;
+            assert!(matcher.matches(&nk));
+            assert!(!matcher.matches(&ipv4(127, 0, 0, 2)));
+        }
+
+        // network ID scoping
+        let nk = ipv4(127, 0, 0, 1);
+        assert!(subnet(127, 0, 0, 0, 120, Some(nk.network_id)).matches(&nk));
+        assert!(!subnet(127, 0, 0, 0, 120, Some(nk.network_id + 1)).matches(&nk));
+        assert!(!subnet(127, 0, 1, 0, 120, Some(nk.network_id)).matches(&nk));
+    }
+
+    #[test]
+    fn test_shed_inbound_under_prune_pressure() {
+        let mut conn_opts = ConnectionOptions::default();
+        conn_opts.prune.soft_num_clients = 2;
+        conn_opts.prune.soft_max_clients_per_host = 1;
+        conn_opts.prune.inbound_shed_threshold = 4;
+        conn_opts.prune.inbound_shed_window = 60;
+
+        let mut sim = PeerNetworkPruneSim::new(conn_opts);
+        let shared_ip = PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x01,0x01]);
+
+        // several prune cycles where inbound peers keep piling in from the same host
+        let mut port = 40000;
+        for _ in 0..5 {
+            for _ in 0..5 {
+                port += 1;
+                sim.add_peer(shared_ip.clone(), port, 100, false, 1000 - (port as u64 - 40000));
+            }
+            sim.prune();
+        }
+
+        let now = get_epoch_time_secs();
+        assert!(sim.network.recent_inbound_prune_count(now) > 4 * 4);
+
+        let newcomer = PeerNetworkPruneSim::make_neighbor(PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x09,0x09]), 40000, 200);
+
+        // with a fixed seed, the shed decision is the same every time
+        let mut num_shed = 0;
+        for seed in 0..64 {
+            sim.network.prune_rng_seed = Some(seed);
+            let shed = match sim.network.can_register_peer(&newcomer.addr, false) {
+                Err(net_error::TooManyPeers) => true,
+                Ok(()) => false,
+                res => panic!("unexpected result: {:?}", res)
+            };
+            for _ in 0..10 {
+                assert_eq!(sim.network.can_register_peer(&newcomer.addr, false).is_err(), shed);
+            }
+            if shed {
+                num_shed += 1;
+            }
+        }
+
+        // we're far enough over the threshold that most connections are shed
+        assert!(num_shed > 32);
+        sim.network.prune_rng_seed = None;
+
+        // outbound and allowlisted peers are never shed
+        for _ in 0..50 {
+            assert!(sim.network.can_register_peer(&newcomer.addr, true).is_ok());
+        }
+        sim.network.connection_opts.allowlist.push(NeighborMatcher::Exact(newcomer.addr.clone()));
+        for _ in 0..50 {
+            assert!(sim.network.can_register_peer(&newcomer.addr, false).is_ok());
+        }
+        sim.network.connection_opts.allowlist.clear();
+
+        // once the prunes age out of the window, we accept again
+        for prune_time in sim.network.recent_inbound_prunes.iter_mut() {
+            *prune_time -= 61;
+        }
+        for _ in 0..50 {
+            assert!(sim.network.can_register_peer(&newcomer.addr, false).is_ok());
+        }
+        assert_eq!(sim.network.recent_inbound_prune_count(now), 0);
+    }
+
     #[test]
     fn test_dispatch_requests_relay() {
         let neighbor = make_test_neighbor(2100);
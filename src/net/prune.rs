@@ -25,8 +25,11 @@ use net::p2p::*;
 use net::Error as net_error;
 
 use net::connection::ConnectionOptions;
+use net::connection::PruneConfig;
 
+use net::chat::ConversationP2P;
 use net::chat::NeighborStats;
+use net::chat::NeighborHealthPoint;
 
 use net::poll::NetworkState;
 use net::poll::NetworkPollState;
@@ -38,6 +41,11 @@ use net::neighbors::*;
 
 use util::db::Error as db_error;
 use util::db::DBConn;
+use util::secp256k1::Secp256k1PublicKey;
+
+use burnchains::Burnchain;
+use burnchains::BurnchainView;
+use chainstate::burn::ConsensusHash;
 
 use std::net::SocketAddr;
 use std::net::Shutdown;
@@ -46,6 +54,8 @@ use std::collections::VecDeque;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::cmp::Ordering;
+use std::time::Duration;
+use std::time::Instant;
 
 use util::log;
 use util::get_epoch_time_secs;
@@ -53,6 +63,91 @@ use util::get_epoch_time_secs;
 use rand::prelude::*;
 use rand::thread_rng;
 
+/// Timings and peer counts of the prune passes, for catching performance regressions.
+/// The `last_*` fields describe the most recent call to prune_frontier; the `total_*` fields
+/// accumulate over all calls.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct PruneMetrics {
+    pub num_passes: u64,
+
+    pub last_denylist_pruned: u64,
+    pub last_num_inbound: u64,
+    pub last_num_outbound: u64,
+    pub last_inbound_ip_pruned: u64,
+    pub last_inbound_reserved_pruned: u64,
+    pub last_outbound_org_pruned: u64,
+
+    pub last_inbound_ip_duration: Duration,
+    pub last_outbound_org_duration: Duration,
+    pub total_inbound_ip_duration: Duration,
+    pub total_outbound_org_duration: Duration,
+}
+
+/// The inputs the prune passes consider for a single neighbor, for debugging prune decisions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NeighborPruneInputs {
+    pub outbound: bool,
+    pub org: Option<u32>,           // None if the neighbor isn't in the peer DB
+    pub uptime_bucket: u64,
+    pub prune_score: f64,
+    pub peer_version: u32,
+    pub num_reconnects: u64,
+    pub raw_health_score: f64,      // health without penalizing stale data points
+    pub health_score: f64,          // health as used by the prune passes
+    pub relay_value: u64,
+    pub rtt_ewma_ms: Option<f64>,   // None if we've never timed a reply from the neighbor
+}
+
+/// A connected neighbor, as recorded in a PeerTableSnapshot
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PeerTableSnapshotEntry {
+    pub peer_version: u32,
+    pub network_id: u32,
+    pub addrbytes: PeerAddress,
+    pub port: u16,
+    pub outbound: bool,
+    pub org: Option<u32>,               // None if the neighbor isn't in the peer DB
+    pub first_contact_time: u64,
+    pub num_reconnects: u64,
+    pub stats_peer_version: u32,        // protocol version the neighbor reported
+    pub healthpoints: Vec<(bool, u64)>, // (success, time) of each health point, oldest first
+    #[serde(default)]
+    pub rtt_ewma_ms: Option<f64>,
+    #[serde(default)]
+    pub peer_services: u16,             // services bitfield the neighbor advertised
+    #[serde(default)]
+    pub relay_value: u64,               // how many blocks, microblocks, and transactions messages it sent us
+    pub preserved: bool,                // preserved, allowlisted, or mid-walk -- never pruned
+    pub denied: bool,                   // denylisted -- always pruned
+}
+
+/// Everything the prune passes look at in our peer table, so prune decisions can be replayed
+/// offline with prune_replay().
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PeerTableSnapshot {
+    pub snapshot_time: u64,
+    pub network_id: u32,
+    pub frontier_orgs: Vec<u32>,        // orgs of all the peers in our frontier, not just connected ones
+    pub entries: Vec<PeerTableSnapshotEntry>,
+    #[serde(default)]
+    pub in_ibd: bool,                   // whether we were in initial block download
+    #[serde(default = "default_snapshot_num_neighbors")]
+    pub num_neighbors: u64,             // hard limit on outbound peers
+}
+
+fn default_snapshot_num_neighbors() -> u64 {
+    ConnectionOptions::default().num_neighbors
+}
+
+/// Who a prune pass removed, and which orgs it left under-represented.
+/// All lists are sorted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PruneOutcome {
+    pub pruned_inbound: Vec<NeighborKey>,
+    pub pruned_outbound: Vec<NeighborKey>,
+    pub under_represented_orgs: Vec<u32>,
+}
+
 impl PeerNetwork {
     /// Find out which organizations have which of our outbound neighbors.
     /// Gives back a map from the organization ID to the list of (neighbor, neighbor-stats) tuples
@@ -106,27 +201,121 @@ impl PeerNetwork {
         Ok(org_neighbor)
     }
 
+    /// Which (geometric) uptime bucket does a neighbor fall into as of `now`?  Buckets are
+    /// powers of `base`, which must be greater than 1.0.
+    fn uptime_bucket(stats: &NeighborStats, now: u64, base: f64) -> u64 {
+        let uptime = (now - stats.first_contact_time) as f64;
+        fmax!(0.0, uptime.log(base).round()) as u64
+    }
+
+    /// Which latency tier does a neighbor fall into?  Tiers are `tier_ms` wide, and a neighbor we've
+    /// never timed a reply from falls into the slowest tier.
+    fn latency_tier(stats: &NeighborStats, tier_ms: u64) -> u64 {
+        match stats.rtt_ewma_ms {
+            Some(rtt) => (fmax!(0.0, rtt) as u64) / tier_ms,
+            None => u64::max_value()
+        }
+    }
+
+    /// Does a neighbor advertise any of the services in `protected_services`?
+    fn offers_protected_services(stats: &NeighborStats, protected_services: u16) -> bool {
+        (stats.peer_services & protected_services) != 0
+    }
+
+    /// How desirable is a neighbor to keep, as of `now`?  This is the weighted sum of
+    /// * its uptime bucket (see uptime_bucket()),
+    /// * its health score, from 0.0 to 1.0,
+    /// * how much it has relayed to us, as log2(1 + relay value), and
+    /// * how responsive it is, as 1 / (1 + round-trip seconds), or 0.0 if we've never timed it,
+    /// with config's uptime, health, relay, and latency weights.  Higher is better.
+    pub fn prune_score(config: &PruneConfig, stats: &NeighborStats, now: u64) -> f64 {
+        let mut score = config.uptime_weight * (PeerNetwork::uptime_bucket(stats, now, config.uptime_log_base) as f64);
+        if config.health_weight > 0.0 {
+            score += config.health_weight * stats.get_health_score();
+        }
+        if config.relay_weight > 0.0 {
+            score += config.relay_weight * (1.0 + (stats.get_relay_value() as f64)).log2();
+        }
+        if config.latency_weight > 0.0 {
+            let responsiveness = match stats.rtt_ewma_ms {
+                Some(rtt) => 1.0 / (1.0 + fmax!(0.0, rtt) / 1000.0),
+                None => 0.0
+            };
+            score += config.latency_weight * responsiveness;
+        }
+        score
+    }
+
     /// Sort function for a neighbor list in order to compare by by uptime and health.
-    /// Bucket uptime geometrically by powers of 2 -- a node that's been up for X seconds is
-    /// likely to be up for X more seconds, so we only really want to distinguish between nodes that
-    /// have wildly different uptimes.
-    /// Within uptime buckets, sort by health.
-    fn compare_neighbor_uptime_health(stats1: &NeighborStats, stats2: &NeighborStats) -> Ordering {
+    /// Less-desirable neighbors sort first, and are pruned first.  The precedence is:
+    /// * protected services (if config.protected_services is nonzero).  Peers offering a service
+    /// we rely on, like serving historical data, are costly to lose, so they're pruned only once
+    /// no other peer in their org is left to prune.
+    /// * latency tier (if config.latency_tier_ms is nonzero).  Peers with slower round-trip
+    /// times are less desirable to a node that also serves RPC, so this overrides everything else.
+    /// * prune score (see prune_score()).  Bucket uptime geometrically by powers of
+    /// config.uptime_log_base (2 by default) -- a node that's been up for X seconds is likely to be
+    /// up for X more seconds, so we only really want to distinguish between nodes that have wildly
+    /// different uptimes.  The default weights score by uptime bucket alone; weighting health,
+    /// relay value, or latency lets a deployment trade some stability for those.
+    /// * protocol version (if config.prefer_newer_versions is set).  Peers running older
+    /// protocol versions are less desirable, since they can't relay newer message types.
+    /// * churn.  Peers that have disconnected and reconnected more often in the past are less
+    /// desirable, since a recently-reconnected churner otherwise looks brand new.
+    /// * health.
+    /// * neighbor key (address, then port).  This is arbitrary, but it makes prune decisions
+    /// reproducible instead of dependent on hash map iteration order.  Any new criteria go before it.
+    fn compare_neighbor_uptime_health(config: &PruneConfig, nk1: &NeighborKey, stats1: &NeighborStats, nk2: &NeighborKey, stats2: &NeighborStats) -> Ordering {
+        // sort by protected services (unprotected is worse)
+        let protected_1 = PeerNetwork::offers_protected_services(stats1, config.protected_services);
+        let protected_2 = PeerNetwork::offers_protected_services(stats2, config.protected_services);
+        if protected_1 != protected_2 {
+            return protected_1.cmp(&protected_2);
+        }
+
+        // sort by latency tier (slower is worse)
+        if config.latency_tier_ms > 0 {
+            let latency_tier_1 = PeerNetwork::latency_tier(stats1, config.latency_tier_ms);
+            let latency_tier_2 = PeerNetwork::latency_tier(stats2, config.latency_tier_ms);
+
+            if latency_tier_1 > latency_tier_2 {
+                return Ordering::Less;
+            }
+            if latency_tier_1 < latency_tier_2 {
+                return Ordering::Greater;
+            }
+        }
+
         let now = get_epoch_time_secs();
-        let uptime_1 = (now - stats1.first_contact_time) as f64;
-        let uptime_2 = (now - stats2.first_contact_time) as f64;
+        let score_1 = PeerNetwork::prune_score(config, stats1, now);
+        let score_2 = PeerNetwork::prune_score(config, stats2, now);
+
+        if score_1 < score_2 {
+            return Ordering::Less;
+        }
+        if score_1 > score_2 {
+            return Ordering::Greater;
+        }
 
-        let uptime_bucket_1 = fmax!(0.0, uptime_1.log2().round()) as u64;
-        let uptime_bucket_2 = fmax!(0.0, uptime_2.log2().round()) as u64;
+        // same score; sort by protocol version (older is worse)
+        if config.prefer_newer_versions {
+            if stats1.peer_version < stats2.peer_version {
+                return Ordering::Less;
+            }
+            if stats1.peer_version > stats2.peer_version {
+                return Ordering::Greater;
+            }
+        }
 
-        if uptime_bucket_1 < uptime_bucket_2 {
+        // sort by churn (more reconnects is worse)
+        if stats1.num_reconnects > stats2.num_reconnects {
             return Ordering::Less;
         }
-        if uptime_bucket_1 > uptime_bucket_1 {
+        if stats1.num_reconnects < stats2.num_reconnects {
             return Ordering::Greater;
         }
 
-        // same bucket; sort by health 
+        // same churn; sort by health 
         let health_1 = stats1.get_health_score();
         let health_2 = stats2.get_health_score();
         
@@ -137,28 +326,42 @@ impl PeerNetwork {
             return Ordering::Greater;
         }
 
-        // flip a coin
-        let mut rng = thread_rng();
-        if rng.next_u32() % 2 == 0 {
-            return Ordering::Less;
-        }
-        else {
-            return Ordering::Greater;
+        // fully tied; sort by key
+        (&nk1.addrbytes, nk1.port).cmp(&(&nk2.addrbytes, nk2.port))
+    }
+
+    /// Get the RNG the outbound org pass and inbound shedding sample with.  It's seeded from
+    /// prune_rng_seed if set, and at random otherwise.
+    fn prune_rng(&self) -> StdRng {
+        let mut seed = [0u8; 32];
+        match self.prune_rng_seed {
+            Some(prune_rng_seed) => {
+                for i in 0..8 {
+                    seed[i] = ((prune_rng_seed >> (8 * i)) & 0xff) as u8;
+                }
+            },
+            None => {
+                thread_rng().fill_bytes(&mut seed);
+            }
         }
-        // return Ordering::Equal;
+        StdRng::from_seed(seed)
     }
 
-    /// Sample an org based on its weight
-    fn sample_org_by_neighbor_count(org_weights: &HashMap<u32, usize>) -> u32 {
-        let mut rng = thread_rng();
+    /// Sample an org based on its weight.
+    /// Orgs are visited in ascending order, so the same RNG state always picks the same org.
+    fn sample_org_by_neighbor_count(org_weights: &HashMap<u32, usize>, rng: &mut StdRng) -> u32 {
         let mut total = 0;
         for (_, count) in org_weights.iter() {
             total += count;
         }
 
+        let mut orgs : Vec<u32> = org_weights.keys().cloned().collect();
+        orgs.sort();
+
         let sample = rng.gen_range(0, total);
         let mut offset = 0;
-        for (org, count) in org_weights.iter() {
+        for org in orgs.iter() {
+            let count = org_weights.get(org).expect("BUG: org has no weight");
             if *count == 0 {
                 continue;
             }
@@ -172,61 +375,84 @@ impl PeerNetwork {
     }
 
     /// If we have an overabundance of outbound connections, then remove ones from overrepresented
-    /// organizations that are unhealthy or very-recently discovered.
+    /// organizations that are unhealthy or very-recently discovered.  Peers we first contacted
+    /// less than prune.min_age_before_prune_secs ago haven't had a chance to prove themselves yet,
+    /// so they're left alone unless we're over the hard limit of num_neighbors.
+    /// While we're in initial block download, the only goal is to get back under num_neighbors.
     /// Returns the list of neighbor keys to remove.
     fn prune_frontier_outbound_orgs(&mut self, preserve: &HashSet<usize>) -> Result<Vec<NeighborKey>, net_error> {
         let num_outbound = PeerNetwork::count_outbound_conversations(&self.peers);
-        if num_outbound <= self.connection_opts.soft_num_neighbors {
+
+        // during initial block download, only enforce the hard cap
+        let target_outbound = if self.in_ibd { self.connection_opts.num_neighbors } else { self.connection_opts.prune.soft_num_neighbors };
+        if num_outbound <= target_outbound {
             return Ok(vec![]);
         }
 
-        let mut org_neighbors = self.org_neighbor_distribution(self.peerdb.conn(), preserve)?;
+        let mut preserve = preserve.clone();
+        if num_outbound <= self.connection_opts.num_neighbors {
+            let now = get_epoch_time_secs();
+            for (event_id, convo) in self.peers.iter() {
+                if convo.stats.outbound && convo.stats.first_contact_time + self.connection_opts.prune.min_age_before_prune_secs > now {
+                    test_debug!("{:?}: too soon to prune {:?}", &self.local_peer, &convo.to_neighbor_key());
+                    preserve.insert(*event_id);
+                }
+            }
+        }
+
+        let mut org_neighbors = self.org_neighbor_distribution(self.peerdb.conn(), &preserve)?;
         let mut ret = vec![];
-        let orgs : Vec<u32> = org_neighbors.keys().map(|o| {let r = *o; r }).collect();
+        let mut orgs : Vec<u32> = org_neighbors.keys().map(|o| {let r = *o; r }).collect();
+        orgs.sort();
 
         for org in orgs.iter() {
-            // sort each neighbor list by uptime and health.
+            // sort each neighbor list by prune score, which is the uptime bucket by default.
             // bucket uptime geometrically by powers of 2 -- a node that's been up for X seconds is
             // likely to be up for X more seconds, so we only really want to distinguish between nodes that
             // have wildly different uptimes.
-            // Within uptime buckets, sort by health.
+            // Within equal scores, sort by protocol version, churn, then health, then key (see
+            // compare_neighbor_uptime_health).  If latency tiers are enabled, they come first, and
+            // peers offering protected services come after everyone else.
             match org_neighbors.get_mut(&org) {
                 None => {},
                 Some(ref mut neighbor_infos) => {
-                    neighbor_infos.sort_by(|&(ref _nk1, ref stats1), &(ref _nk2, ref stats2)| PeerNetwork::compare_neighbor_uptime_health(stats1, stats2));
+                    neighbor_infos.sort_by(|&(ref nk1, ref stats1), &(ref nk2, ref stats2)| PeerNetwork::compare_neighbor_uptime_health(&self.connection_opts.prune, nk1, stats1, nk2, stats2));
                 }
             }
         }
 
         // don't let a single organization have more than
-        // soft_max_neighbors_per_org neighbors.
-        for org in orgs.iter() {
-            match org_neighbors.get_mut(&org) {
-                None => {},
-                Some(ref mut neighbor_infos) => {
-                    if neighbor_infos.len() as u64 > self.connection_opts.soft_max_neighbors_per_org {
-                        test_debug!("Org {} has {} neighbors (more than {} soft limit)", org, neighbor_infos.len(), self.connection_opts.soft_max_neighbors_per_org);
-                        for i in 0..((neighbor_infos.len() as u64) - self.connection_opts.soft_max_neighbors_per_org) {
-                            let (neighbor_key, _) = neighbor_infos[i as usize].clone();
+        // soft_max_neighbors_per_org neighbors (unless we're in initial block download, and
+        // can't afford to lose the peers we're syncing from just to balance orgs).
+        if !self.in_ibd {
+            for org in orgs.iter() {
+                match org_neighbors.get_mut(&org) {
+                    None => {},
+                    Some(ref mut neighbor_infos) => {
+                        if neighbor_infos.len() as u64 > self.connection_opts.prune.soft_max_neighbors_per_org {
+                            test_debug!("Org {} has {} neighbors (more than {} soft limit)", org, neighbor_infos.len(), self.connection_opts.prune.soft_max_neighbors_per_org);
+                            for i in 0..((neighbor_infos.len() as u64) - self.connection_opts.prune.soft_max_neighbors_per_org) {
+                                let (neighbor_key, _) = neighbor_infos[i as usize].clone();
 
-                            test_debug!("{:?}: Prune {:?} because its org ({}) dominates our peer table", &self.local_peer, &neighbor_key, org);
+                                test_debug!("{:?}: Prune {:?} because its org ({}) dominates our peer table", &self.local_peer, &neighbor_key, org);
                             
-                            ret.push(neighbor_key);
+                                ret.push(neighbor_key);
                             
-                            // don't prune too many
-                            if num_outbound - (ret.len() as u64) <= self.connection_opts.soft_num_neighbors {
-                                break;
+                                // don't prune too many
+                                if num_outbound - (ret.len() as u64) <= target_outbound {
+                                    break;
+                                }
+                            }
+                            for _ in 0..ret.len() {
+                                neighbor_infos.remove(0);
                             }
-                        }
-                        for _ in 0..ret.len() {
-                            neighbor_infos.remove(0);
                         }
                     }
                 }
             }
         }
 
-        if num_outbound - (ret.len() as u64) <= self.connection_opts.soft_num_neighbors {
+        if num_outbound - (ret.len() as u64) <= target_outbound {
             // pruned enough 
             debug!("{:?}: removed {} outbound peers out of {}", &self.local_peer, ret.len(), num_outbound);
             return Ok(ret);
@@ -234,8 +460,9 @@ impl PeerNetwork {
 
         // select an org at random proportional to its popularity, and remove a neighbor 
         // at random proportional to how unhealthy and short-lived it is.
-        test_debug!("{:?}: Prune outbound neighbor set of {} down to {}", &self.local_peer, num_outbound, self.connection_opts.soft_num_neighbors);
-        while num_outbound - (ret.len() as u64) > self.connection_opts.soft_num_neighbors {
+        test_debug!("{:?}: Prune outbound neighbor set of {} down to {}", &self.local_peer, num_outbound, target_outbound);
+        let mut rng = self.prune_rng();
+        while num_outbound - (ret.len() as u64) > target_outbound {
             let mut weighted_sample : HashMap<u32, usize> = HashMap::new();
             for (org, neighbor_info) in org_neighbors.iter() {
                 if neighbor_info.len() > 0 {
@@ -247,7 +474,7 @@ impl PeerNetwork {
                 break;
             }
 
-            let prune_org = PeerNetwork::sample_org_by_neighbor_count(&weighted_sample);
+            let prune_org = PeerNetwork::sample_org_by_neighbor_count(&weighted_sample, &mut rng);
 
             match org_neighbors.get_mut(&prune_org) {
                 None => {
@@ -272,8 +499,8 @@ impl PeerNetwork {
     /// Returns the list of IPs to remove.
     /// Removes them in reverse order they are added
     fn prune_frontier_inbound_ip(&mut self, preserve: &HashSet<usize>) -> Vec<NeighborKey> {
-        let num_inbound = (self.num_peers() as u64) - PeerNetwork::count_outbound_conversations(&self.peers);
-        if num_inbound <= self.connection_opts.soft_num_clients {
+        let num_inbound = (self.peers.len() as u64) - PeerNetwork::count_outbound_conversations(&self.peers);
+        if num_inbound <= self.connection_opts.prune.soft_num_clients {
             return vec![];
         }
 
@@ -298,26 +525,44 @@ impl PeerNetwork {
             }
         }
 
-        // sort in order by first-contact time (oldest first)
+        // sort in order by protected services (protected first), then by relay value (most useful
+        // first, if prune.prefer_useful_inbound is set), then by first-contact time (oldest first),
+        // then by port.  The tail of each list gets pruned.
+        let protected_services = self.connection_opts.prune.protected_services;
+        let prefer_useful_inbound = self.connection_opts.prune.prefer_useful_inbound;
         for (_, stats_list) in ip_neighbor.iter_mut() {
-            stats_list.sort_by(|&(ref _e1, ref _nk1, ref stats1), &(ref _e2, ref _nk2, ref stats2)| {
-                if stats1.first_contact_time < stats2.first_contact_time {
+            stats_list.sort_by(|&(ref _e1, ref nk1, ref stats1), &(ref _e2, ref nk2, ref stats2)| {
+                let protected_1 = PeerNetwork::offers_protected_services(stats1, protected_services);
+                let protected_2 = PeerNetwork::offers_protected_services(stats2, protected_services);
+                if protected_1 != protected_2 {
+                    protected_2.cmp(&protected_1)
+                }
+                else if prefer_useful_inbound && stats1.get_relay_value() != stats2.get_relay_value() {
+                    stats2.get_relay_value().cmp(&stats1.get_relay_value())
+                }
+                else if stats1.first_contact_time < stats2.first_contact_time {
                     Ordering::Less
                 }
                 else if stats1.first_contact_time > stats2.first_contact_time {
                     Ordering::Greater
                 }
                 else {
-                    Ordering::Equal
+                    nk1.port.cmp(&nk2.port)
                 }
             });
         }
 
+        // worst first, in case the prune budget cuts the list short: the most crowded IPs go
+        // first, and within each IP, the end of the list goes first.
+        let mut addrs : Vec<PeerAddress> = ip_neighbor.keys().cloned().collect();
+        addrs.sort_by(|addr1, addr2| ip_neighbor[addr2].len().cmp(&ip_neighbor[addr1].len()).then_with(|| addr1.cmp(addr2)));
+
         let mut to_remove = vec![];
-        for (addrbytes, neighbor_info) in ip_neighbor.iter_mut() {
-            if (neighbor_info.len() as u64) > self.connection_opts.soft_max_clients_per_host {
-                debug!("{:?}: Starting to have too many inbound connections from {:?}; will close the last {:?}", &self.local_peer, &addrbytes, (neighbor_info.len() as u64) - self.connection_opts.soft_max_clients_per_host);
-                for i in (self.connection_opts.soft_max_clients_per_host as usize)..neighbor_info.len() {
+        for addrbytes in addrs.iter() {
+            let neighbor_info = &ip_neighbor[addrbytes];
+            if (neighbor_info.len() as u64) > self.connection_opts.prune.soft_max_clients_per_host {
+                debug!("{:?}: Starting to have too many inbound connections from {:?}; will close the last {:?}", &self.local_peer, &addrbytes, (neighbor_info.len() as u64) - self.connection_opts.prune.soft_max_clients_per_host);
+                for i in ((self.connection_opts.prune.soft_max_clients_per_host as usize)..neighbor_info.len()).rev() {
                     to_remove.push(neighbor_info[i].1.clone());
                 }
             }
@@ -327,6 +572,142 @@ impl PeerNetwork {
         to_remove
     }
 
+    /// Prune inbound peers that are eating into the outbound slots we hold in reserve.
+    /// Inbound and outbound peers together get soft_num_clients + soft_num_neighbors slots, of
+    /// which at least reserved_outbound_slots are kept for outbound peers -- otherwise, slots freed
+    /// by outbound pruning would just get taken by new inbound peers.  Outbound peers beyond
+    /// soft_num_neighbors don't count, since the outbound passes are there to trim them.
+    /// Does nothing if reserved_outbound_slots is 0.
+    /// Returns the list of inbound peers to remove, newest first.
+    fn prune_frontier_inbound_reserved(&mut self, preserve: &HashSet<usize>) -> Vec<NeighborKey> {
+        if self.connection_opts.prune.reserved_outbound_slots == 0 {
+            return vec![];
+        }
+
+        let num_outbound = PeerNetwork::count_outbound_conversations(&self.peers);
+        let num_inbound = (self.peers.len() as u64) - num_outbound;
+
+        let total_slots = self.connection_opts.prune.soft_num_clients + self.connection_opts.prune.soft_num_neighbors;
+        let used_outbound_slots = if num_outbound < self.connection_opts.prune.soft_num_neighbors { num_outbound } else { self.connection_opts.prune.soft_num_neighbors };
+        let outbound_slots = if used_outbound_slots > self.connection_opts.prune.reserved_outbound_slots { used_outbound_slots } else { self.connection_opts.prune.reserved_outbound_slots };
+        let inbound_slots = if total_slots > outbound_slots { total_slots - outbound_slots } else { 0 };
+        if num_inbound <= inbound_slots {
+            return vec![];
+        }
+
+        let mut inbound : Vec<(NeighborKey, u64)> = vec![];
+        for (nk, event_id) in self.events.iter() {
+            if preserve.contains(event_id) {
+                continue;
+            }
+            match self.peers.get(event_id) {
+                Some(ref convo) => {
+                    if !convo.stats.outbound {
+                        inbound.push((nk.clone(), convo.stats.first_contact_time));
+                    }
+                },
+                None => {}
+            }
+        }
+
+        // newest first
+        inbound.sort_by(|&(ref nk1, ref time1), &(ref nk2, ref time2)| time2.cmp(time1).then_with(|| (&nk1.addrbytes, nk1.port).cmp(&(&nk2.addrbytes, nk2.port))));
+
+        let to_remove : Vec<NeighborKey> = inbound
+            .into_iter()
+            .take((num_inbound - inbound_slots) as usize)
+            .map(|(nk, _)| nk)
+            .collect();
+
+        debug!("{:?}: removed {} inbound peers out of {} to keep {} outbound slots free", &self.local_peer, to_remove.len(), num_inbound, outbound_slots);
+        to_remove
+    }
+
+    /// Find the orgs that have fewer than soft_max_neighbors_per_org outbound neighbors.
+    /// Considers every org in the frontier, not just the ones we're connected to.
+    /// Returns the org IDs in ascending order.
+    fn find_under_represented_orgs(&self) -> Result<Vec<u32>, net_error> {
+        let frontier_orgs = PeerDB::get_frontier_orgs(self.peerdb.conn(), self.local_peer.network_id)
+            .map_err(net_error::DBError)?;
+        self.under_represented_orgs_among(frontier_orgs)
+    }
+
+    /// Find the orgs, out of the given frontier orgs and the orgs of our outbound neighbors, that
+    /// have fewer than soft_max_neighbors_per_org outbound neighbors.  Org 0 is where peers with
+    /// no ASN mapping land, so it's never considered under-represented.
+    /// Returns the org IDs in ascending order.
+    fn under_represented_orgs_among(&self, frontier_orgs: Vec<u32>) -> Result<Vec<u32>, net_error> {
+        let org_neighbors = self.org_neighbor_distribution(self.peerdb.conn(), &HashSet::new())?;
+
+        let mut orgs : HashSet<u32> = frontier_orgs.into_iter().collect();
+        for org in org_neighbors.keys() {
+            orgs.insert(*org);
+        }
+
+        let mut under_represented : Vec<u32> = orgs
+            .into_iter()
+            .filter(|org| {
+                if **org == 0 {
+                    return false;
+                }
+                let count = match org_neighbors.get(org) {
+                    Some(neighbor_infos) => neighbor_infos.len() as u64,
+                    None => 0
+                };
+                count < self.connection_opts.prune.soft_max_neighbors_per_org
+            })
+            .collect();
+
+        under_represented.sort();
+        Ok(under_represented)
+    }
+
+    /// Safety valve for outbound pruning: remove from the prune list any neighbor that would be
+    /// the sole remaining outbound peer of its org, so that we don't partition ourselves from a
+    /// region of the network we can only reach through that org.  Pruning proceeds unchecked if
+    /// we're over the hard cap on outbound connections.
+    /// Returns the list of neighbor keys that are still safe to remove.
+    fn spare_sole_org_peers(&self, pruned: &Vec<NeighborKey>) -> Result<Vec<NeighborKey>, net_error> {
+        let num_outbound = PeerNetwork::count_outbound_conversations(&self.peers);
+        if num_outbound > self.connection_opts.num_neighbors {
+            return Ok(pruned.clone());
+        }
+
+        let org_neighbors = self.org_neighbor_distribution(self.peerdb.conn(), &HashSet::new())?;
+
+        let mut neighbor_orgs : HashMap<NeighborKey, u32> = HashMap::new();
+        let mut remaining : HashMap<u32, u64> = HashMap::new();
+        for (org, neighbor_infos) in org_neighbors.iter() {
+            for (nk, _) in neighbor_infos.iter() {
+                neighbor_orgs.insert(nk.clone(), *org);
+            }
+            remaining.insert(*org, neighbor_infos.len() as u64);
+        }
+
+        let mut ret = vec![];
+        for nk in pruned.iter() {
+            let org = match neighbor_orgs.get(nk) {
+                Some(org) => *org,
+                None => {
+                    // not an outbound peer we know the org of
+                    ret.push(nk.clone());
+                    continue;
+                }
+            };
+
+            let count = remaining.get_mut(&org).expect("BUG: org has no neighbor count");
+            if *count <= 1 {
+                info!("{:?}: Will not prune {:?}: it is our last outbound peer in org {}", &self.local_peer, nk, org);
+                continue;
+            }
+
+            *count -= 1;
+            ret.push(nk.clone());
+        }
+
+        Ok(ret)
+    }
+
     /// Dump our peer table
     #[cfg(test)]
     pub fn dump_peer_table(&mut self) -> (Vec<String>, Vec<String>) {
@@ -349,9 +730,364 @@ impl PeerNetwork {
         (inbound, outbound)
     }
 
-    /// Prune our frontier.  Ignore connections in the preserve set.
+    /// Count a prune of the given neighbor
+    fn record_prune(counts: &mut HashMap<NeighborKey, u64>, times: &mut HashMap<NeighborKey, u64>, nk: &NeighborKey, now: u64) -> () {
+        let c = match counts.get(nk) {
+            Some(c) => *c,
+            None => 0
+        };
+        counts.insert(nk.clone(), c + 1);
+        times.insert(nk.clone(), now);
+    }
+
+    /// Disconnect a neighbor chosen by a prune pass, and count the prune against it.  The neighbor
+    /// may have closed the connection itself since it was chosen, in which case we didn't prune
+    /// it and don't count it.
+    /// Returns true if we did the disconnecting.
+    fn prune_neighbor(&mut self, nk: &NeighborKey, outbound: bool, now: u64) -> bool {
+        let org_opt = if outbound {
+            match PeerDB::get_peer(self.peerdb.conn(), nk.network_id, &nk.addrbytes, nk.port) {
+                Ok(Some(peer)) => Some(peer.org),
+                Ok(None) => None,
+                Err(e) => {
+                    warn!("{:?}: failed to look up {:?}: {:?}", &self.local_peer, nk, &e);
+                    None
+                }
+            }
+        }
+        else {
+            None
+        };
+
+        if !self.deregister_neighbor(nk) {
+            test_debug!("{:?}: {:?} already disconnected", &self.local_peer, nk);
+            return false;
+        }
+
+        if outbound {
+            PeerNetwork::record_prune(&mut self.prune_outbound_counts, &mut self.prune_outbound_times, nk, now);
+            if let Some(org) = org_opt {
+                let count = self.prune_org_counts.entry(org).or_insert(0);
+                *count += 1;
+            }
+            if let Err(e) = self.set_prune_cooldown(nk, now) {
+                warn!("{:?}: failed to set prune cooldown on {:?}: {:?}", &self.local_peer, nk, &e);
+            }
+        }
+        else {
+            PeerNetwork::record_prune(&mut self.prune_inbound_counts, &mut self.prune_inbound_times, nk, now);
+            self.recent_inbound_prunes.push_back(now);
+        }
+        true
+    }
+
+    /// Keep the neighbor walk from selecting an outbound peer we just pruned for the next
+    /// prune.prune_cooldown_secs seconds, so we don't reconnect to it only to prune it again.
+    fn set_prune_cooldown(&mut self, nk: &NeighborKey, now: u64) -> Result<(), net_error> {
+        if self.connection_opts.prune.prune_cooldown_secs == 0 {
+            return Ok(());
+        }
+
+        let cooldown_until = now.saturating_add(self.connection_opts.prune.prune_cooldown_secs);
+        let mut tx = self.peerdb.tx_begin().map_err(net_error::DBError)?;
+        PeerDB::set_prune_cooldown(&mut tx, nk.network_id, &nk.addrbytes, nk.port, cooldown_until)
+            .map_err(net_error::DBError)?;
+        tx.commit().map_err(|e| net_error::DBError(db_error::SqliteError(e)))?;
+        Ok(())
+    }
+
+    /// How many inbound peers did we prune within prune.inbound_shed_window seconds of `now`?
+    pub fn recent_inbound_prune_count(&mut self, now: u64) -> u64 {
+        let window = self.connection_opts.prune.inbound_shed_window;
+        while let Some(prune_time) = self.recent_inbound_prunes.front().cloned() {
+            if prune_time + window > now {
+                break;
+            }
+            self.recent_inbound_prunes.pop_front();
+        }
+        self.recent_inbound_prunes.len() as u64
+    }
+
+    /// Should we refuse a new inbound connection to shed load?  If we've recently pruned more
+    /// than prune.inbound_shed_threshold inbound peers, we're persistently over capacity, and
+    /// accepting more would only give us more to prune.  We refuse with a probability that grows
+    /// with how far over the threshold we are, and stop once the prunes age out of the window.
+    pub fn should_shed_inbound(&mut self, now: u64) -> bool {
+        let threshold = self.connection_opts.prune.inbound_shed_threshold;
+        if threshold == 0 {
+            return false;
+        }
+
+        let recent = self.recent_inbound_prune_count(now);
+        if recent <= threshold {
+            return false;
+        }
+
+        let shed_probability = ((recent - threshold) as f64) / (recent as f64);
+        self.prune_rng().gen::<f64>() < shed_probability
+    }
+
+    /// Halve the prune counts of neighbors that haven't been pruned (or decayed) within the last
+    /// prune.count_decay_interval seconds, and forget neighbors whose counts reach 0.
+    fn decay_counts(counts: &mut HashMap<NeighborKey, u64>, times: &mut HashMap<NeighborKey, u64>, interval: u64, now: u64) -> () {
+        let mut to_remove = vec![];
+        for (nk, count) in counts.iter_mut() {
+            let last_time = match times.get(nk) {
+                Some(t) => *t,
+                None => 0
+            };
+            if last_time + interval > now {
+                continue;
+            }
+
+            *count = *count / 2;
+            times.insert(nk.clone(), now);
+            if *count == 0 {
+                to_remove.push(nk.clone());
+            }
+        }
+
+        for nk in to_remove.iter() {
+            counts.remove(nk);
+            times.remove(nk);
+        }
+    }
+
+    /// Decay the inbound and outbound prune counts, so a peer that was pruned a while ago and has
+    /// since behaved isn't treated like a chronic offender forever.
+    pub fn decay_prune_counts(&mut self, now: u64) -> () {
+        let interval = self.connection_opts.prune.count_decay_interval;
+        if interval == 0 {
+            return;
+        }
+        PeerNetwork::decay_counts(&mut self.prune_inbound_counts, &mut self.prune_inbound_times, interval, now);
+        PeerNetwork::decay_counts(&mut self.prune_outbound_counts, &mut self.prune_outbound_times, interval, now);
+    }
+
+    /// Forget how often we pruned the given neighbor
+    pub fn reset_prune_counts(&mut self, nk: &NeighborKey) -> () {
+        self.prune_inbound_counts.remove(nk);
+        self.prune_inbound_times.remove(nk);
+        self.prune_outbound_counts.remove(nk);
+        self.prune_outbound_times.remove(nk);
+    }
+
+    /// Get the inputs the prune passes would consider for a connected neighbor.
+    /// Returns None if we're not connected to it.
+    pub fn neighbor_prune_inputs(&self, nk: &NeighborKey) -> Option<NeighborPruneInputs> {
+        let event_id = self.events.get(nk)?;
+        let convo = self.peers.get(event_id)?;
+
+        let org = match PeerDB::get_peer(self.peerdb.conn(), nk.network_id, &nk.addrbytes, nk.port) {
+            Ok(Some(peer)) => Some(peer.org),
+            Ok(None) => None,
+            Err(e) => {
+                warn!("{:?}: failed to look up {:?}: {:?}", &self.local_peer, nk, &e);
+                None
+            }
+        };
+
+        Some(NeighborPruneInputs {
+            outbound: convo.stats.outbound,
+            org: org,
+            uptime_bucket: PeerNetwork::uptime_bucket(&convo.stats, get_epoch_time_secs(), self.connection_opts.prune.uptime_log_base),
+            prune_score: PeerNetwork::prune_score(&self.connection_opts.prune, &convo.stats, get_epoch_time_secs()),
+            peer_version: convo.stats.peer_version,
+            num_reconnects: convo.stats.num_reconnects,
+            raw_health_score: convo.stats.get_raw_health_score(),
+            health_score: convo.stats.get_health_score(),
+            relay_value: convo.stats.get_relay_value(),
+            rtt_ewma_ms: convo.stats.rtt_ewma_ms,
+        })
+    }
+
+    /// Get the (up to) n orgs we've pruned the most outbound peers from, and how many we pruned
+    /// from each, most-pruned first.  An org that keeps topping this list is either
+    /// over-represented in our frontier, or soft_max_neighbors_per_org is set too low.
+    pub fn top_pruned_orgs(&self, n: usize) -> Vec<(u32, u64)> {
+        let mut org_counts : Vec<(u32, u64)> = self.prune_org_counts.iter().map(|(org, count)| (*org, *count)).collect();
+        org_counts.sort_by(|&(org1, count1), &(org2, count2)| count2.cmp(&count1).then(org1.cmp(&org2)));
+        org_counts.truncate(n);
+        org_counts
+    }
+
+    /// Trim a prune pass's picks (worst first) to what's left of prune.max_prune_per_cycle, given
+    /// that `num_pruned` peers were already picked this cycle.  The rest wait for the next cycle.
+    fn within_prune_budget(&self, pass: &str, mut picks: Vec<NeighborKey>, num_pruned: u64) -> Vec<NeighborKey> {
+        let budget = self.connection_opts.prune.max_prune_per_cycle;
+        if budget == 0 {
+            return picks;
+        }
+
+        let remaining = budget.saturating_sub(num_pruned);
+        if (picks.len() as u64) > remaining {
+            debug!("{:?}: prune budget of {} reached; deferring {} of {} {} prunes to the next cycle", &self.local_peer, budget, (picks.len() as u64) - remaining, picks.len(), pass);
+            picks.truncate(remaining as usize);
+        }
+        picks
+    }
+
+    /// Get how many outbound neighbors we have in each org, most first (ties broken by org ID).
+    /// Outbound neighbors that aren't in the peer DB aren't counted.  An org far ahead of the rest
+    /// is over-represented in our frontier.
+    pub fn org_distribution_summary(&self) -> Vec<(u32, usize)> {
+        let org_neighbors = match self.org_neighbor_distribution(self.peerdb.conn(), &HashSet::new()) {
+            Ok(org_neighbors) => org_neighbors,
+            Err(e) => {
+                warn!("{:?}: failed to get org distribution: {:?}", &self.local_peer, &e);
+                return vec![];
+            }
+        };
+
+        let mut org_counts : Vec<(u32, usize)> = org_neighbors.iter().map(|(org, neighbor_infos)| (*org, neighbor_infos.len())).collect();
+        org_counts.sort_by(|&(org1, count1), &(org2, count2)| count2.cmp(&count1).then(org1.cmp(&org2)));
+        org_counts
+    }
+
+    /// Get the timings and peer counts of the prune passes so far
+    pub fn get_prune_metrics(&self) -> &PruneMetrics {
+        &self.prune_metrics
+    }
+
+    /// Get the event IDs of the connections the prune passes must leave alone: the ones in the
+    /// preserve set, plus allowlisted peers and the neighbors our walk is waiting on.
+    fn prune_safe_set(&self, preserve: &HashSet<usize>) -> HashSet<usize> {
+        let mut safe = preserve.clone();
+        for (nk, event_id) in self.events.iter() {
+            if self.is_allowed(nk) || self.walking_neighbors.contains(nk) {
+                safe.insert(*event_id);
+            }
+        }
+        safe
+    }
+
+    /// Swap out a marginal outbound peer for a better one.  Unlike prune_frontier(), this acts when
+    /// we're exactly at soft_num_neighbors outbound peers: it picks the least-healthy peer in our
+    /// most-represented org, and drops it if the peer DB has a candidate in an org that would be
+    /// less represented even after taking its place.  The freed slot goes to the next neighbor
+    /// we connect to, which is preferentially drawn from under-represented orgs.
+    /// Runs at most once every prune.rebalance_interval seconds.  Ignores connections in the
+    /// preserve set, as well as allowlisted peers.
+    /// Returns the neighbor we dropped, if any.
+    pub fn rebalance_frontier(&mut self, preserve: &HashSet<usize>) -> Result<Option<NeighborKey>, net_error> {
+        let now = get_epoch_time_secs();
+        if self.connection_opts.prune.rebalance_interval == 0 || now < self.rebalance_deadline || self.in_ibd {
+            return Ok(None);
+        }
+
+        let num_outbound = PeerNetwork::count_outbound_conversations(&self.peers);
+        if num_outbound != self.connection_opts.prune.soft_num_neighbors {
+            return Ok(None);
+        }
+        self.rebalance_deadline = now + self.connection_opts.prune.rebalance_interval;
+
+        let safe = self.prune_safe_set(preserve);
+        let org_counts : HashMap<u32, u64> = self.org_neighbor_distribution(self.peerdb.conn(), &HashSet::new())?
+            .into_iter()
+            .map(|(org, neighbor_infos)| (org, neighbor_infos.len() as u64))
+            .collect();
+        let org_neighbors = self.org_neighbor_distribution(self.peerdb.conn(), &safe)?;
+
+        // most-represented org we can drop a peer from (lowest org ID on a tie)
+        let mut worst_org_opt : Option<(u32, u64)> = None;
+        for (org, neighbor_infos) in org_neighbors.iter() {
+            if neighbor_infos.len() == 0 {
+                continue;
+            }
+            let count = *org_counts.get(org).expect("BUG: org has no neighbor count");
+            worst_org_opt = match worst_org_opt {
+                Some((worst_org, worst_count)) if worst_count > count || (worst_count == count && worst_org < *org) => Some((worst_org, worst_count)),
+                _ => Some((*org, count))
+            };
+        }
+
+        let (worst_org, worst_count) = match worst_org_opt {
+            Some(worst) => worst,
+            None => {
+                return Ok(None);
+            }
+        };
+
+        // least-healthy peer in that org, falling back to the prune order on a tie
+        let config = &self.connection_opts.prune;
+        let worst_peer = org_neighbors.get(&worst_org).expect("BUG: no neighbors in worst org")
+            .iter()
+            .min_by(|(nk1, stats1), (nk2, stats2)| {
+                stats1.get_health_score().partial_cmp(&stats2.get_health_score())
+                    .unwrap_or(Ordering::Equal)
+                    .then_with(|| PeerNetwork::compare_neighbor_uptime_health(config, nk1, stats1, nk2, stats2))
+            })
+            .map(|&(ref nk, _)| nk.clone())
+            .expect("BUG: no neighbors in worst org");
+
+        // a candidate is only better if its org stays less represented than worst_org was
+        let frontier_orgs = PeerDB::get_frontier_orgs(self.peerdb.conn(), self.local_peer.network_id)
+            .map_err(net_error::DBError)?;
+        let candidate_orgs : Vec<u32> = frontier_orgs
+            .into_iter()
+            .filter(|org| {
+                let count = match org_counts.get(org) {
+                    Some(count) => *count,
+                    None => 0
+                };
+                count + 1 < worst_count
+            })
+            .collect();
+
+        // we're connected to fewer than num_outbound of these, so asking for one more than that
+        // finds an unconnected one if there is any
+        let candidates = PeerDB::get_random_neighbors_in_orgs(self.peerdb.conn(), self.local_peer.network_id, &candidate_orgs, (num_outbound + 1) as u32, self.chain_view.burn_block_height)
+            .map_err(net_error::DBError)?;
+
+        let candidate_opt = candidates
+            .into_iter()
+            .find(|candidate| !self.is_registered(&candidate.addr) && !self.is_denied(&candidate.addr));
+
+        match candidate_opt {
+            Some(candidate) => {
+                info!("{:?}: Rebalance: drop {:?} (org {}) to make room for peers like {:?} (org {})", &self.local_peer, &worst_peer, worst_org, &candidate.addr, candidate.org);
+                self.prune_neighbor(&worst_peer, true, now);
+                Ok(Some(worst_peer))
+            },
+            None => {
+                test_debug!("{:?}: Rebalance: no better candidate than {:?} (org {})", &self.local_peer, &worst_peer, worst_org);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Prune our frontier.  Ignore connections in the preserve set, as well as allowlisted peers.
     pub fn prune_frontier(&mut self, preserve: &HashSet<usize>) -> () {
-        let pruned_by_ip = self.prune_frontier_inbound_ip(preserve);
+        let now = get_epoch_time_secs();
+        self.decay_prune_counts(now);
+
+        // denylisted peers go first, whether or not we'd otherwise preserve them
+        let denied : Vec<NeighborKey> = self.events.keys().filter(|nk| self.is_denied(nk)).cloned().collect();
+        self.prune_metrics.last_denylist_pruned = denied.len() as u64;
+
+        for nk in denied.iter() {
+            debug!("{:?}: prune denylisted peer {:?}", &self.local_peer, nk);
+            self.deregister_neighbor(nk);
+        }
+
+        let safe = self.prune_safe_set(preserve);
+
+        let num_outbound = PeerNetwork::count_outbound_conversations(&self.peers);
+        self.prune_metrics.last_num_outbound = num_outbound;
+        self.prune_metrics.last_num_inbound = (self.peers.len() as u64) - num_outbound;
+
+        // how many peers the passes below have picked so far, against prune.max_prune_per_cycle
+        let mut num_pruned = 0;
+
+        let inbound_ip_start = Instant::now();
+        let pruned_by_ip = self.prune_frontier_inbound_ip(&safe);
+        let pruned_by_ip = self.within_prune_budget("inbound IP", pruned_by_ip, num_pruned);
+        num_pruned += pruned_by_ip.len() as u64;
+        let inbound_ip_duration = inbound_ip_start.elapsed();
+
+        self.prune_metrics.last_inbound_ip_duration = inbound_ip_duration;
+        self.prune_metrics.total_inbound_ip_duration += inbound_ip_duration;
+        self.prune_metrics.last_inbound_ip_pruned = pruned_by_ip.len() as u64;
 
         if pruned_by_ip.len() > 0 {
             test_debug!("{:?}: remove {} inbound peers by shared IP", &self.local_peer, pruned_by_ip.len());
@@ -359,18 +1095,37 @@ impl PeerNetwork {
 
         for prune in pruned_by_ip.iter() {
             test_debug!("{:?}: prune by IP: {:?}", &self.local_peer, prune);
-            self.deregister_neighbor(&prune);
-            
-            if !self.prune_inbound_counts.contains_key(prune) {
-                self.prune_inbound_counts.insert(prune.clone(), 1);
-            }
-            else {
-                let c = self.prune_inbound_counts.get(prune).unwrap().to_owned();
-                self.prune_inbound_counts.insert(prune.clone(), c + 1);
+            self.prune_neighbor(prune, false, now);
+        }
+
+        let outbound_org_start = Instant::now();
+        let pruned_by_org = self.prune_frontier_outbound_orgs(&safe).unwrap_or(vec![]);
+        let mut pruned_by_org = match self.spare_sole_org_peers(&pruned_by_org) {
+            Ok(pruned) => pruned,
+            Err(e) => {
+                warn!("{:?}: failed to check for sole-org peers: {:?}", &self.local_peer, &e);
+                pruned_by_org
             }
+        };
+
+        // the budget can't keep us over the hard limit on outbound peers
+        let over_hard_limit = num_outbound.saturating_sub(self.connection_opts.num_neighbors) as usize;
+        let within_budget = self.within_prune_budget("outbound org", pruned_by_org.clone(), num_pruned);
+        if within_budget.len() < over_hard_limit && within_budget.len() < pruned_by_org.len() {
+            pruned_by_org.truncate(over_hard_limit);
+            warn!("{:?}: exceeding the prune budget of {} to get {} outbound peers down to the hard limit of {}: pruning {} more",
+                  &self.local_peer, self.connection_opts.prune.max_prune_per_cycle, num_outbound, self.connection_opts.num_neighbors, pruned_by_org.len() - within_budget.len());
+        }
+        else {
+            pruned_by_org = within_budget;
         }
-       
-        let pruned_by_org = self.prune_frontier_outbound_orgs(preserve).unwrap_or(vec![]);
+        num_pruned += pruned_by_org.len() as u64;
+        let outbound_org_duration = outbound_org_start.elapsed();
+
+        self.prune_metrics.last_outbound_org_duration = outbound_org_duration;
+        self.prune_metrics.total_outbound_org_duration += outbound_org_duration;
+        self.prune_metrics.last_outbound_org_pruned = pruned_by_org.len() as u64;
+        self.prune_metrics.num_passes += 1;
 
         if pruned_by_org.len() > 0 {
             test_debug!("{:?}: remove {} outbound peers by shared Org", &self.local_peer, pruned_by_org.len());
@@ -378,20 +1133,37 @@ impl PeerNetwork {
 
         for prune in pruned_by_org.iter() {
             test_debug!("{:?}: prune by Org: {:?}", &self.local_peer, prune);
-            self.deregister_neighbor(&prune);
+            self.prune_neighbor(prune, true, now);
+        }
 
-            if !self.prune_outbound_counts.contains_key(prune) {
-                self.prune_outbound_counts.insert(prune.clone(), 1);
-            }
-            else {
-                let c = self.prune_outbound_counts.get(prune).unwrap().to_owned();
-                self.prune_outbound_counts.insert(prune.clone(), c + 1);
+        // reserve outbound slots against the outbound peers we kept
+        let pruned_by_reservation = self.prune_frontier_inbound_reserved(&safe);
+        let pruned_by_reservation = self.within_prune_budget("inbound reservation", pruned_by_reservation, num_pruned);
+        self.prune_metrics.last_inbound_reserved_pruned = pruned_by_reservation.len() as u64;
+
+        for prune in pruned_by_reservation.iter() {
+            test_debug!("{:?}: prune to reserve outbound slots: {:?}", &self.local_peer, prune);
+            self.prune_neighbor(prune, false, now);
+        }
+
+        match self.find_under_represented_orgs() {
+            Ok(orgs) => {
+                test_debug!("{:?}: under-represented orgs: {:?}", &self.local_peer, &orgs);
+                self.under_represented_orgs = orgs;
+            },
+            Err(e) => {
+                warn!("{:?}: failed to find under-represented orgs: {:?}", &self.local_peer, &e);
             }
         }
 
+        if pruned_by_ip.len() > 0 || pruned_by_reservation.len() > 0 || pruned_by_org.len() > 0 {
+            let org_counts : Vec<String> = self.org_distribution_summary().iter().map(|(org, count)| format!("{}:{}", org, count)).collect();
+            debug!("{:?}: Outbound peers per org after prune: {}", &self.local_peer, org_counts.join(", "));
+        }
+
         #[cfg(test)]
         {
-            if pruned_by_ip.len() > 0 || pruned_by_org.len() > 0 {
+            if pruned_by_ip.len() > 0 || pruned_by_reservation.len() > 0 || pruned_by_org.len() > 0 {
                 let (mut inbound, mut outbound) = self.dump_peer_table();
 
                 inbound.sort();
@@ -410,3 +1182,1728 @@ impl PeerNetwork {
         }
     }
 }
+
+impl PeerNetwork {
+    /// Record everything the prune passes would look at in our peer table right now, given the
+    /// set of connections to preserve.  Entries are sorted by address and port.
+    pub fn peer_table_snapshot(&self, preserve: &HashSet<usize>) -> Result<PeerTableSnapshot, net_error> {
+        let safe = self.prune_safe_set(preserve);
+
+        let mut entries = vec![];
+        for (nk, event_id) in self.events.iter() {
+            let convo = match self.peers.get(event_id) {
+                Some(convo) => convo,
+                None => {
+                    continue;
+                }
+            };
+
+            let org = PeerDB::get_peer(self.peerdb.conn(), nk.network_id, &nk.addrbytes, nk.port)
+                .map_err(net_error::DBError)?
+                .map(|peer| peer.org);
+
+            entries.push(PeerTableSnapshotEntry {
+                peer_version: nk.peer_version,
+                network_id: nk.network_id,
+                addrbytes: nk.addrbytes.clone(),
+                port: nk.port,
+                outbound: convo.stats.outbound,
+                org: org,
+                first_contact_time: convo.stats.first_contact_time,
+                num_reconnects: convo.stats.num_reconnects,
+                stats_peer_version: convo.stats.peer_version,
+                healthpoints: convo.stats.healthpoints.iter().map(|hp| (hp.success, hp.time)).collect(),
+                rtt_ewma_ms: convo.stats.rtt_ewma_ms,
+                peer_services: convo.stats.peer_services,
+                relay_value: convo.stats.get_relay_value(),
+                preserved: safe.contains(event_id),
+                denied: self.is_denied(nk),
+            });
+        }
+        entries.sort_by(|e1, e2| (&e1.addrbytes, e1.port).cmp(&(&e2.addrbytes, e2.port)));
+
+        let mut frontier_orgs = PeerDB::get_frontier_orgs(self.peerdb.conn(), self.local_peer.network_id)
+            .map_err(net_error::DBError)?;
+        frontier_orgs.sort();
+        frontier_orgs.dedup();
+
+        Ok(PeerTableSnapshot {
+            snapshot_time: get_epoch_time_secs(),
+            network_id: self.local_peer.network_id,
+            frontier_orgs: frontier_orgs,
+            entries: entries,
+            in_ibd: self.in_ibd,
+            num_neighbors: self.connection_opts.num_neighbors,
+        })
+    }
+
+    /// Prune our frontier, and report who got removed.
+    pub fn prune_frontier_outcome(&mut self, preserve: &HashSet<usize>) -> PruneOutcome {
+        let mut before : Vec<(NeighborKey, bool)> = vec![];
+        for (nk, event_id) in self.events.iter() {
+            if let Some(convo) = self.peers.get(event_id) {
+                before.push((nk.clone(), convo.stats.outbound));
+            }
+        }
+
+        self.prune_frontier(preserve);
+
+        let mut pruned_inbound = vec![];
+        let mut pruned_outbound = vec![];
+        for (nk, outbound) in before.into_iter() {
+            if self.is_registered(&nk) {
+                continue;
+            }
+            if outbound {
+                pruned_outbound.push(nk);
+            }
+            else {
+                pruned_inbound.push(nk);
+            }
+        }
+
+        pruned_inbound.sort_by(|nk1, nk2| (&nk1.addrbytes, nk1.port).cmp(&(&nk2.addrbytes, nk2.port)));
+        pruned_outbound.sort_by(|nk1, nk2| (&nk1.addrbytes, nk1.port).cmp(&(&nk2.addrbytes, nk2.port)));
+
+        PruneOutcome {
+            pruned_inbound: pruned_inbound,
+            pruned_outbound: pruned_outbound,
+            under_represented_orgs: self.under_represented_orgs.clone(),
+        }
+    }
+
+    /// Make a conversation with the given neighbor that has no socket behind it.
+    fn new_socketless_conversation(&self, event_id: usize, nk: &NeighborKey, outbound: bool, first_contact_time: u64) -> ConversationP2P {
+        let addr = nk.addrbytes.to_socketaddr(nk.port);
+        let mut convo = ConversationP2P::new(self.local_peer.network_id, self.peer_version, &self.burnchain, &addr, &self.connection_opts, outbound, event_id);
+        convo.peer_version = nk.peer_version;
+        convo.peer_network_id = nk.network_id;
+        convo.stats.first_contact_time = first_contact_time;
+        convo
+    }
+
+    /// Make a PeerNetwork with no sockets and an in-memory peer DB, to replay prune decisions in.
+    fn new_replay_network(network_id: u32, conn_opts: ConnectionOptions) -> Result<PeerNetwork, net_error> {
+        let burnchain = Burnchain::new(&"".to_string(), &"bitcoin".to_string(), &"regtest".to_string())
+            .expect("BUG: regtest burnchain is not supported");
+
+        let burnchain_view = BurnchainView {
+            burn_block_height: 0,
+            burn_consensus_hash: ConsensusHash([0u8; 20]),
+            burn_stable_block_height: 0,
+            burn_stable_consensus_hash: ConsensusHash([0u8; 20]),
+            last_consensus_hashes: HashMap::new()
+        };
+
+        let db = PeerDB::connect_memory(network_id, 0, 0, "http://localhost".into(), &vec![], &vec![])
+            .map_err(net_error::DBError)?;
+        let local_peer = PeerDB::get_local_peer(db.conn())
+            .map_err(net_error::DBError)?;
+        let peer_version = burnchain.peer_version;
+
+        Ok(PeerNetwork::new(db, local_peer, peer_version, burnchain, burnchain_view, conn_opts))
+    }
+}
+
+/// Replay the prune passes on a recorded peer table snapshot, without any sockets or the node's
+/// peer DB.  Uptimes and health points are taken relative to when the snapshot was recorded, and
+/// the outbound org pass samples with an RNG seeded from `seed`, so a snapshot recorded from a
+/// network with prune_rng_seed set to `seed` replays to the same outcome that network's next
+/// prune_frontier_outcome() gives.  Connection options other than the prune thresholds (such as
+/// the hard num_neighbors limit) take their defaults.
+pub fn prune_replay(snapshot: &PeerTableSnapshot, thresholds: &PruneConfig, seed: u64) -> Result<PruneOutcome, net_error> {
+    let conn_opts = ConnectionOptions {
+        prune: thresholds.clone(),
+        num_neighbors: snapshot.num_neighbors,
+        ..ConnectionOptions::default()
+    };
+    conn_opts.validate()?;
+
+    let mut network = PeerNetwork::new_replay_network(snapshot.network_id, conn_opts)?;
+    network.prune_rng_seed = Some(seed);
+    network.in_ibd = snapshot.in_ibd;
+
+    let now = get_epoch_time_secs();
+    let shift = if now > snapshot.snapshot_time { now - snapshot.snapshot_time } else { 0 };
+    let public_key = Secp256k1PublicKey::from_private(&network.local_peer.private_key);
+
+    let mut denied_inbound = vec![];
+    let mut denied_outbound = vec![];
+    let mut preserve = HashSet::new();
+
+    for (i, entry) in snapshot.entries.iter().enumerate() {
+        let nk = NeighborKey {
+            peer_version: entry.peer_version,
+            network_id: entry.network_id,
+            addrbytes: entry.addrbytes.clone(),
+            port: entry.port,
+        };
+
+        if entry.denied {
+            // prune_frontier drops these first thing
+            if entry.outbound {
+                denied_outbound.push(nk);
+            }
+            else {
+                denied_inbound.push(nk);
+            }
+            continue;
+        }
+
+        // the prune passes look up orgs in the peer DB
+        if let Some(org) = entry.org {
+            let neighbor = Neighbor {
+                addr: nk.clone(),
+                public_key: public_key.clone(),
+                expire_block: i64::max_value() as u64,
+                last_contact_time: now,
+                whitelisted: 0,
+                blacklisted: 0,
+                asn: org,
+                org: org,
+                in_degree: 1,
+                out_degree: 1
+            };
+
+            let mut tx = network.peerdb.tx_begin().map_err(net_error::DBError)?;
+            PeerDB::try_insert_peer(&mut tx, &neighbor).map_err(net_error::DBError)?;
+            tx.commit().map_err(|e| net_error::DBError(db_error::SqliteError(e)))?;
+        }
+
+        let event_id = i + 1;
+        let mut convo = network.new_socketless_conversation(event_id, &nk, entry.outbound, entry.first_contact_time + shift);
+        convo.stats.num_reconnects = entry.num_reconnects;
+        convo.stats.peer_version = entry.stats_peer_version;
+        convo.stats.rtt_ewma_ms = entry.rtt_ewma_ms;
+        convo.stats.peer_services = entry.peer_services;
+        if entry.relay_value > 0 {
+            convo.stats.msg_rx_counts.insert(StacksMessageID::Transaction, entry.relay_value);
+        }
+        for &(success, time) in entry.healthpoints.iter() {
+            convo.stats.healthpoints.push_back(NeighborHealthPoint {
+                success: success,
+                time: time + shift
+            });
+        }
+
+        network.peers.insert(event_id, convo);
+        network.events.insert(nk, event_id);
+
+        if entry.preserved {
+            preserve.insert(event_id);
+        }
+    }
+
+    let mut outcome = network.prune_frontier_outcome(&preserve);
+    outcome.pruned_inbound.append(&mut denied_inbound);
+    outcome.pruned_outbound.append(&mut denied_outbound);
+    outcome.pruned_inbound.sort_by(|nk1, nk2| (&nk1.addrbytes, nk1.port).cmp(&(&nk2.addrbytes, nk2.port)));
+    outcome.pruned_outbound.sort_by(|nk1, nk2| (&nk1.addrbytes, nk1.port).cmp(&(&nk2.addrbytes, nk2.port)));
+    outcome.under_represented_orgs = network.under_represented_orgs_among(snapshot.frontier_orgs.clone())?;
+
+    Ok(outcome)
+}
+
+/// Harness for exercising the prune passes without any sockets.  Build up a peer table of
+/// socket-less conversations, with the org, uptime, and health of each peer given explicitly,
+/// then run prune_frontier() and see who got removed.
+#[cfg(test)]
+pub mod sim {
+    use super::*;
+    use net::chat::NeighborHealthPoint;
+    use net::chat::NUM_HEALTH_POINTS;
+
+    use burnchains::Burnchain;
+    use burnchains::BurnchainView;
+    use burnchains::BurnchainHeaderHash;
+    use chainstate::burn::ConsensusHash;
+
+    use util::secp256k1::Secp256k1PublicKey;
+
+    pub struct PeerNetworkPruneSim {
+        pub network: PeerNetwork,
+        next_event_id: usize,
+    }
+
+    impl PeerNetworkPruneSim {
+        pub fn new(conn_opts: ConnectionOptions) -> PeerNetworkPruneSim {
+            let first_burn_hash = BurnchainHeaderHash::from_hex("0000000000000000000000000000000000000000000000000000000000000000").unwrap();
+
+            let burnchain = Burnchain {
+                peer_version: 0x012345678,
+                network_id: 0x9abcdef0,
+                chain_name: "bitcoin".to_string(),
+                network_name: "testnet".to_string(),
+                working_dir: "/nope".to_string(),
+                consensus_hash_lifetime: 24,
+                stable_confirmations: 7,
+                first_block_height: 50,
+                first_block_hash: first_burn_hash.clone(),
+            };
+
+            let mut burnchain_view = BurnchainView {
+                burn_block_height: 12345,
+                burn_consensus_hash: ConsensusHash::from_hex("1111111111111111111111111111111111111111").unwrap(),
+                burn_stable_block_height: 12339,
+                burn_stable_consensus_hash: ConsensusHash::from_hex("2222222222222222222222222222222222222222").unwrap(),
+                last_consensus_hashes: HashMap::new()
+            };
+            burnchain_view.make_test_data();
+
+            let db = PeerDB::connect_memory(0x9abcdef0, 0, 23456, "http://test-prune.com".into(), &vec![], &vec![]).unwrap();
+            let local_peer = PeerDB::get_local_peer(db.conn()).unwrap();
+            let network = PeerNetwork::new(db, local_peer, 0x12345678, burnchain, burnchain_view, conn_opts);
+
+            PeerNetworkPruneSim {
+                network: network,
+                next_event_id: 1,
+            }
+        }
+
+        pub fn make_neighbor(addrbytes: PeerAddress, port: u16, org: u32) -> Neighbor {
+            Neighbor {
+                addr: NeighborKey {
+                    peer_version: 0x12345678,
+                    network_id: 0x9abcdef0,
+                    addrbytes: addrbytes,
+                    port: port,
+                },
+                public_key: Secp256k1PublicKey::from_hex("02fa66b66f8971a8cd4d20ffded09674e030f0f33883f337f34b95ad4935bac0e3").unwrap(),
+                expire_block: 23456,
+                last_contact_time: get_epoch_time_secs(),
+                whitelisted: 0,
+                blacklisted: 0,
+                asn: org,
+                org: org,
+                in_degree: 1,
+                out_degree: 1
+            }
+        }
+
+        /// Add a socket-less conversation with the given neighbor to the network, and store the
+        /// neighbor in the peer DB so its org can be looked up.
+        pub fn add_conversation(network: &mut PeerNetwork, event_id: usize, neighbor: &Neighbor, outbound: bool, first_contact_time: u64) -> () {
+            {
+                let mut tx = network.peerdb.tx_begin().unwrap();
+                PeerDB::try_insert_peer(&mut tx, neighbor).unwrap();
+                tx.commit().unwrap();
+            }
+
+            let convo = network.new_socketless_conversation(event_id, &neighbor.addr, outbound, first_contact_time);
+            network.peers.insert(event_id, convo);
+            network.events.insert(neighbor.addr.clone(), event_id);
+        }
+
+        /// Add a peer in the given org that we've been talking to for `uptime` seconds.
+        pub fn add_peer(&mut self, addrbytes: PeerAddress, port: u16, org: u32, outbound: bool, uptime: u64) -> NeighborKey {
+            let neighbor = PeerNetworkPruneSim::make_neighbor(addrbytes, port, org);
+            let event_id = self.next_event_id;
+            self.next_event_id += 1;
+
+            PeerNetworkPruneSim::add_conversation(&mut self.network, event_id, &neighbor, outbound, get_epoch_time_secs() - uptime);
+            neighbor.addr
+        }
+
+        /// Give a peer a full set of fresh health points, of which `health_score` are successes.
+        pub fn set_health_score(&mut self, neighbor_key: &NeighborKey, health_score: f64) -> () {
+            let event_id = *self.network.events.get(neighbor_key).expect("BUG: no such sim peer");
+            let convo = self.network.peers.get_mut(&event_id).expect("BUG: no such sim peer");
+
+            let num_successes = (health_score * (NUM_HEALTH_POINTS as f64)).round() as usize;
+            let now = get_epoch_time_secs();
+
+            convo.stats.healthpoints.clear();
+            for i in 0..NUM_HEALTH_POINTS {
+                convo.stats.healthpoints.push_back(NeighborHealthPoint {
+                    success: i < num_successes,
+                    time: now
+                });
+            }
+        }
+
+        /// Add a peer in the given org to the peer DB only, as a candidate we could connect to.
+        pub fn add_frontier_peer(&mut self, addrbytes: PeerAddress, port: u16, org: u32) -> NeighborKey {
+            let neighbor = PeerNetworkPruneSim::make_neighbor(addrbytes, port, org);
+            let mut tx = self.network.peerdb.tx_begin().unwrap();
+            PeerDB::try_insert_peer(&mut tx, &neighbor).unwrap();
+            tx.commit().unwrap();
+            neighbor.addr
+        }
+
+        /// Set a peer's moving-average round-trip time, as if we'd timed replies from it.
+        pub fn set_rtt(&mut self, neighbor_key: &NeighborKey, rtt_ms: f64) -> () {
+            let event_id = *self.network.events.get(neighbor_key).expect("BUG: no such sim peer");
+            let convo = self.network.peers.get_mut(&event_id).expect("BUG: no such sim peer");
+            convo.stats.rtt_ewma_ms = Some(rtt_ms);
+        }
+
+        /// Set how much useful data a peer has relayed to us, as if it had sent us `relay_value`
+        /// transactions messages.
+        pub fn set_relay_value(&mut self, neighbor_key: &NeighborKey, relay_value: u64) -> () {
+            let event_id = *self.network.events.get(neighbor_key).expect("BUG: no such sim peer");
+            let convo = self.network.peers.get_mut(&event_id).expect("BUG: no such sim peer");
+            convo.stats.msg_rx_counts.insert(StacksMessageID::Transaction, relay_value);
+        }
+
+        /// Set the services bitfield a peer advertised, as if we'd handshaked with it.
+        pub fn set_services(&mut self, neighbor_key: &NeighborKey, services: u16) -> () {
+            let event_id = *self.network.events.get(neighbor_key).expect("BUG: no such sim peer");
+            let convo = self.network.peers.get_mut(&event_id).expect("BUG: no such sim peer");
+            convo.stats.peer_services = services;
+        }
+
+        pub fn is_connected(&self, neighbor_key: &NeighborKey) -> bool {
+            self.network.is_registered(neighbor_key)
+        }
+
+        /// Run one prune pass.
+        /// Returns the keys of the peers that were removed, sorted by address and port.
+        pub fn prune(&mut self) -> Vec<NeighborKey> {
+            let before : Vec<NeighborKey> = self.network.events.keys().cloned().collect();
+            self.network.prune_frontier(&HashSet::new());
+
+            let mut pruned : Vec<NeighborKey> = before
+                .into_iter()
+                .filter(|nk| !self.network.is_registered(nk))
+                .collect();
+
+            pruned.sort_by(|nk1, nk2| (&nk1.addrbytes, nk1.port).cmp(&(&nk2.addrbytes, nk2.port)));
+            pruned
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::sim::PeerNetworkPruneSim;
+    use serde_json;
+    use net::db::*;
+    use net::chat::NeighborHealthPoint;
+    use net::chat::NUM_HEALTH_POINTS;
+    use net::chat::HEALTH_POINT_LIFETIME;
+
+    fn make_test_prune_network(conn_opts: ConnectionOptions) -> PeerNetwork {
+        PeerNetworkPruneSim::new(conn_opts).network
+    }
+
+    fn make_test_prune_neighbor(addr_byte: u8, port: u16, org: u32) -> Neighbor {
+        PeerNetworkPruneSim::make_neighbor(PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x00,addr_byte]), port, org)
+    }
+
+    fn add_test_prune_peer(network: &mut PeerNetwork, event_id: usize, neighbor: &Neighbor, outbound: bool, first_contact_time: u64) -> () {
+        PeerNetworkPruneSim::add_conversation(network, event_id, neighbor, outbound, first_contact_time)
+    }
+
+    #[test]
+    fn test_compare_neighbor_uptime_health_churn() {
+        let config = PruneConfig::default();
+        let now = get_epoch_time_secs();
+
+        // the churny peer has the higher key, so the key tie-break can't be what orders them
+        let nk_churny = make_test_prune_neighbor(2, 20002, 1).addr;
+        let nk_stable = make_test_prune_neighbor(1, 20001, 1).addr;
+
+        let mut stats_churny = NeighborStats::new(true);
+        stats_churny.first_contact_time = now - 1000;
+        stats_churny.num_reconnects = 10;
+        stats_churny.last_disconnect_time = now - 1001;
+
+        let mut stats_stable = NeighborStats::new(true);
+        stats_stable.first_contact_time = now - 1000;
+        stats_stable.num_reconnects = 1;
+        stats_stable.last_disconnect_time = now - 100000;
+
+        // same uptime and health, but the churny peer sorts first (i.e. is pruned first)
+        assert_eq!(PeerNetwork::compare_neighbor_uptime_health(&config, &nk_churny, &stats_churny, &nk_stable, &stats_stable), Ordering::Less);
+        assert_eq!(PeerNetwork::compare_neighbor_uptime_health(&config, &nk_stable, &stats_stable, &nk_churny, &stats_churny), Ordering::Greater);
+
+        let mut neighbor_infos = vec![(nk_stable.clone(), stats_stable.clone()), (nk_churny.clone(), stats_churny.clone())];
+        neighbor_infos.sort_by(|&(ref nk1, ref stats1), &(ref nk2, ref stats2)| PeerNetwork::compare_neighbor_uptime_health(&config, nk1, stats1, nk2, stats2));
+        assert_eq!(neighbor_infos[0].1.num_reconnects, 10);
+        assert_eq!(neighbor_infos[1].1.num_reconnects, 1);
+
+        // uptime still dominates churn
+        stats_churny.first_contact_time = now - 100000;
+        assert_eq!(PeerNetwork::compare_neighbor_uptime_health(&config, &nk_churny, &stats_churny, &nk_stable, &stats_stable), Ordering::Greater);
+        assert_eq!(PeerNetwork::compare_neighbor_uptime_health(&config, &nk_stable, &stats_stable, &nk_churny, &stats_churny), Ordering::Less);
+    }
+
+    #[test]
+    fn test_compare_neighbor_uptime_health_tie_break() {
+        let config = PruneConfig::default();
+        let now = get_epoch_time_secs();
+
+        let mut stats = NeighborStats::new(true);
+        stats.first_contact_time = now - 1000;
+
+        let nk_low_addr = make_test_prune_neighbor(1, 20002, 1).addr;
+        let nk_high_addr = make_test_prune_neighbor(2, 20001, 1).addr;
+        let nk_high_addr_high_port = make_test_prune_neighbor(2, 20003, 1).addr;
+
+        // address takes precedence over port
+        assert_eq!(PeerNetwork::compare_neighbor_uptime_health(&config, &nk_low_addr, &stats, &nk_high_addr, &stats), Ordering::Less);
+        assert_eq!(PeerNetwork::compare_neighbor_uptime_health(&config, &nk_high_addr, &stats, &nk_low_addr, &stats), Ordering::Greater);
+        assert_eq!(PeerNetwork::compare_neighbor_uptime_health(&config, &nk_high_addr, &stats, &nk_high_addr_high_port, &stats), Ordering::Less);
+        assert_eq!(PeerNetwork::compare_neighbor_uptime_health(&config, &nk_high_addr, &stats, &nk_high_addr, &stats), Ordering::Equal);
+
+        // fully-tied peers in the same org are always pruned in the same order
+        for _ in 0..10 {
+            let mut conn_opts = ConnectionOptions::default();
+            conn_opts.prune.soft_num_neighbors = 1;
+            conn_opts.prune.soft_max_neighbors_per_org = 1;
+
+            let mut network = make_test_prune_network(conn_opts);
+            let neighbor_1 = make_test_prune_neighbor(1, 20001, 1);
+            let neighbor_2 = make_test_prune_neighbor(2, 20001, 1);
+
+            add_test_prune_peer(&mut network, 1, &neighbor_2, true, now - 1000);
+            add_test_prune_peer(&mut network, 2, &neighbor_1, true, now - 1000);
+
+            network.prune_frontier(&HashSet::new());
+
+            assert!(!network.is_registered(&neighbor_1.addr));
+            assert!(network.is_registered(&neighbor_2.addr));
+        }
+    }
+
+    #[test]
+    fn test_compare_neighbor_uptime_health_log_base() {
+        let now = get_epoch_time_secs();
+
+        // the younger peer has the higher key, so the key tie-break alone would prune the older one
+        let nk_older = make_test_prune_neighbor(1, 20001, 1).addr;
+        let nk_younger = make_test_prune_neighbor(2, 20001, 1).addr;
+
+        let mut stats_older = NeighborStats::new(true);
+        stats_older.first_contact_time = now - 1400;
+
+        let mut stats_younger = NeighborStats::new(true);
+        stats_younger.first_contact_time = now - 1000;
+
+        // both round to 2^10 seconds
+        let mut config = PruneConfig::default();
+        assert_eq!(config.uptime_log_base, 2.0);
+        assert_eq!(PeerNetwork::uptime_bucket(&stats_older, now, 2.0), PeerNetwork::uptime_bucket(&stats_younger, now, 2.0));
+        assert_eq!(PeerNetwork::compare_neighbor_uptime_health(&config, &nk_older, &stats_older, &nk_younger, &stats_younger), Ordering::Less);
+
+        // but finer buckets tell them apart
+        config.uptime_log_base = 1.2;
+        assert!(PeerNetwork::uptime_bucket(&stats_older, now, 1.2) > PeerNetwork::uptime_bucket(&stats_younger, now, 1.2));
+        assert_eq!(PeerNetwork::compare_neighbor_uptime_health(&config, &nk_older, &stats_older, &nk_younger, &stats_younger), Ordering::Greater);
+        assert_eq!(PeerNetwork::compare_neighbor_uptime_health(&config, &nk_younger, &stats_younger, &nk_older, &stats_older), Ordering::Less);
+    }
+
+    #[test]
+    fn test_compare_neighbor_uptime_health_latency_tier() {
+        let now = get_epoch_time_secs();
+
+        // the slow peer has the higher key, so the key tie-break alone would prune the fast one
+        let nk_fast = make_test_prune_neighbor(1, 20001, 1).addr;
+        let nk_slow = make_test_prune_neighbor(2, 20001, 1).addr;
+        let nk_untimed = make_test_prune_neighbor(3, 20001, 1).addr;
+
+        let mut stats_fast = NeighborStats::new(true);
+        stats_fast.first_contact_time = now - 1000;
+        stats_fast.add_rtt_sample(20);
+
+        let mut stats_slow = NeighborStats::new(true);
+        stats_slow.first_contact_time = now - 1000;
+        stats_slow.add_rtt_sample(300);
+
+        let mut stats_untimed = NeighborStats::new(true);
+        stats_untimed.first_contact_time = now - 1000;
+
+        let mut config = PruneConfig::default();
+        assert_eq!(config.latency_tier_ms, 0);
+        assert_eq!(PeerNetwork::compare_neighbor_uptime_health(&config, &nk_fast, &stats_fast, &nk_slow, &stats_slow), Ordering::Less);
+
+        config.latency_tier_ms = 100;
+        assert_eq!(PeerNetwork::compare_neighbor_uptime_health(&config, &nk_fast, &stats_fast, &nk_slow, &stats_slow), Ordering::Greater);
+        assert_eq!(PeerNetwork::compare_neighbor_uptime_health(&config, &nk_slow, &stats_slow, &nk_fast, &stats_fast), Ordering::Less);
+
+        // a peer we've never timed is treated as the slowest
+        assert_eq!(PeerNetwork::compare_neighbor_uptime_health(&config, &nk_untimed, &stats_untimed, &nk_slow, &stats_slow), Ordering::Less);
+
+        // within a tier, the other criteria decide
+        config.latency_tier_ms = 1000;
+        assert_eq!(PeerNetwork::compare_neighbor_uptime_health(&config, &nk_fast, &stats_fast, &nk_slow, &stats_slow), Ordering::Less);
+    }
+
+    #[test]
+    fn test_compare_neighbor_prune_score_default_weights() {
+        // the fixed precedence the comparator used before prune scores
+        fn compare_legacy(config: &PruneConfig, nk1: &NeighborKey, stats1: &NeighborStats, nk2: &NeighborKey, stats2: &NeighborStats, now: u64) -> Ordering {
+            PeerNetwork::uptime_bucket(stats1, now, config.uptime_log_base).cmp(&PeerNetwork::uptime_bucket(stats2, now, config.uptime_log_base))
+                .then(if config.prefer_newer_versions { stats1.peer_version.cmp(&stats2.peer_version) } else { Ordering::Equal })
+                .then(stats2.num_reconnects.cmp(&stats1.num_reconnects))
+                .then(stats1.get_health_score().partial_cmp(&stats2.get_health_score()).unwrap())
+                .then((&nk1.addrbytes, nk1.port).cmp(&(&nk2.addrbytes, nk2.port)))
+        }
+
+        let now = get_epoch_time_secs();
+
+        let mut neighbor_infos = vec![];
+        for i in 0..48 {
+            let nk = make_test_prune_neighbor((i % 5) as u8, 20001 + (i % 3) as u16, 1).addr;
+            let mut stats = NeighborStats::new(true);
+            stats.first_contact_time = now - [10, 1000, 1400, 100000][i % 4];
+            stats.peer_version = 0x12345678 + (i % 2) as u32;
+            stats.num_reconnects = (i % 3) as u64;
+            for j in 0..NUM_HEALTH_POINTS {
+                stats.healthpoints.push_back(NeighborHealthPoint { success: j < (i * 7) % (NUM_HEALTH_POINTS + 1), time: now });
+            }
+            stats.msg_rx_counts.insert(StacksMessageID::Transaction, (i * 13 % 7) as u64);
+            if i % 5 != 0 {
+                stats.add_rtt_sample((i * 31 % 500) as u64);
+            }
+            neighbor_infos.push((nk, stats));
+        }
+
+        for prefer_newer_versions in [true, false].iter() {
+            let mut config = PruneConfig::default();
+            config.prefer_newer_versions = *prefer_newer_versions;
+
+            let mut legacy_sorted = neighbor_infos.clone();
+            legacy_sorted.sort_by(|&(ref nk1, ref stats1), &(ref nk2, ref stats2)| compare_legacy(&config, nk1, stats1, nk2, stats2, now));
+
+            let mut sorted = neighbor_infos.clone();
+            sorted.sort_by(|&(ref nk1, ref stats1), &(ref nk2, ref stats2)| PeerNetwork::compare_neighbor_uptime_health(&config, nk1, stats1, nk2, stats2));
+
+            assert_eq!(sorted.iter().map(|(nk, stats)| (nk.clone(), stats.first_contact_time, stats.peer_version, stats.num_reconnects, stats.get_health_score())).collect::<Vec<_>>(),
+                       legacy_sorted.iter().map(|(nk, stats)| (nk.clone(), stats.first_contact_time, stats.peer_version, stats.num_reconnects, stats.get_health_score())).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn test_prune_score_weights() {
+        // returns which of a long-lived but flaky, quiet, and slow peer and a young but healthy,
+        // useful, and fast peer in the same org gets pruned
+        let prune_one = |uptime_weight: f64, health_weight: f64, relay_weight: f64, latency_weight: f64| -> (NeighborKey, NeighborKey, Vec<NeighborKey>) {
+            let mut conn_opts = ConnectionOptions::default();
+            conn_opts.prune.soft_num_neighbors = 1;
+            conn_opts.prune.soft_max_neighbors_per_org = 1;
+            conn_opts.prune.uptime_weight = uptime_weight;
+            conn_opts.prune.health_weight = health_weight;
+            conn_opts.prune.relay_weight = relay_weight;
+            conn_opts.prune.latency_weight = latency_weight;
+            assert!(conn_opts.validate().is_ok());
+
+            let mut sim = PeerNetworkPruneSim::new(conn_opts);
+
+            // the old peer has the higher key, so the key tie-break alone would prune the young one
+            let young_peer = sim.add_peer(PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x0b,0x01]), 20001, 1, true, 100);
+            let old_peer = sim.add_peer(PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x0b,0x02]), 20001, 1, true, 100000);
+
+            sim.set_health_score(&young_peer, 1.0);
+            sim.set_health_score(&old_peer, 0.2);
+            sim.set_relay_value(&young_peer, 1000);
+            sim.set_rtt(&young_peer, 20.0);
+            sim.set_rtt(&old_peer, 2000.0);
+
+            let pruned = sim.prune();
+            (young_peer, old_peer, pruned)
+        };
+
+        // by default, uptime wins
+        let (young_peer, _, pruned) = prune_one(1.0, 0.0, 0.0, 0.0);
+        assert_eq!(pruned, vec![young_peer]);
+
+        // but each of the other terms can outweigh it
+        let (_, old_peer, pruned) = prune_one(0.0, 1.0, 0.0, 0.0);
+        assert_eq!(pruned, vec![old_peer]);
+
+        let (_, old_peer, pruned) = prune_one(0.5, 0.0, 1.0, 0.0);
+        assert_eq!(pruned, vec![old_peer]);
+
+        let (_, old_peer, pruned) = prune_one(0.01, 0.0, 0.0, 1.0);
+        assert_eq!(pruned, vec![old_peer]);
+
+        // and a heavy enough uptime weight wins again
+        let (young_peer, _, pruned) = prune_one(10.0, 1.0, 1.0, 1.0);
+        assert_eq!(pruned, vec![young_peer]);
+    }
+
+    #[test]
+    fn test_validate_prune_weights() {
+        let mut opts = ConnectionOptions::default();
+        for weight in [-1.0, ::std::f64::NAN, ::std::f64::INFINITY].iter() {
+            opts.prune.relay_weight = *weight;
+            match opts.validate() {
+                Err(net_error::InvalidConnectionOptions(_)) => {},
+                res => panic!("accepted relay weight {}: {:?}", weight, res)
+            }
+        }
+
+        opts.prune.relay_weight = 0.0;
+        opts.prune.uptime_weight = 0.0;
+        assert!(opts.validate().is_ok());
+    }
+
+    #[test]
+    fn test_validate_prune_uptime_log_base() {
+        let mut opts = ConnectionOptions::default();
+        assert!(opts.validate().is_ok());
+
+        for base in [1.0, 0.5, 0.0, -2.0, ::std::f64::NAN].iter() {
+            opts.prune.uptime_log_base = *base;
+            match opts.validate() {
+                Err(net_error::InvalidConnectionOptions(_)) => {},
+                res => panic!("accepted log base {}: {:?}", base, res)
+            }
+        }
+
+        opts.prune.uptime_log_base = 1.01;
+        assert!(opts.validate().is_ok());
+    }
+
+    #[test]
+    fn test_prune_config_defaults() {
+        let config : PruneConfig = serde_json::from_str(r#"{"soft_num_neighbors": 5, "uptime_log_base": 1.5}"#).unwrap();
+        let defaults = PruneConfig::default();
+
+        assert_eq!(config.soft_num_neighbors, 5);
+        assert_eq!(config.uptime_log_base, 1.5);
+
+        assert_eq!(config.soft_num_clients, defaults.soft_num_clients);
+        assert_eq!(config.soft_max_neighbors_per_org, defaults.soft_max_neighbors_per_org);
+        assert_eq!(config.soft_max_clients_per_host, defaults.soft_max_clients_per_host);
+        assert_eq!(config.count_decay_interval, defaults.count_decay_interval);
+        assert_eq!(config.prefer_newer_versions, defaults.prefer_newer_versions);
+        assert_eq!(config.reserved_outbound_slots, defaults.reserved_outbound_slots);
+        assert_eq!(config.latency_tier_ms, defaults.latency_tier_ms);
+
+        let empty : PruneConfig = serde_json::from_str("{}").unwrap();
+        assert_eq!(empty, defaults);
+
+        let roundtrip : PruneConfig = serde_json::from_str(&serde_json::to_string(&config).unwrap()).unwrap();
+        assert_eq!(roundtrip, config);
+    }
+
+    #[test]
+    fn test_prune_under_represented_orgs() {
+        let mut conn_opts = ConnectionOptions::default();
+        conn_opts.prune.soft_num_neighbors = 3;
+        conn_opts.prune.soft_max_neighbors_per_org = 2;
+
+        let mut network = make_test_prune_network(conn_opts);
+        let now = get_epoch_time_secs();
+
+        // org 1 is over-represented, org 2 is under-represented, and org 3 is known but not
+        // connected at all.  Org 0 has no ASN mapping, so it doesn't count.
+        let org_1_neighbors = vec![make_test_prune_neighbor(1, 20001, 1), make_test_prune_neighbor(2, 20002, 1), make_test_prune_neighbor(3, 20003, 1)];
+        let org_2_neighbors = vec![make_test_prune_neighbor(4, 20004, 2)];
+        let org_3_neighbor = make_test_prune_neighbor(5, 20005, 3);
+        let org_0_neighbor = make_test_prune_neighbor(6, 20006, 0);
+
+        for (i, neighbor) in org_1_neighbors.iter().enumerate() {
+            add_test_prune_peer(&mut network, i, neighbor, true, now - 1000);
+        }
+        for (i, neighbor) in org_2_neighbors.iter().enumerate() {
+            add_test_prune_peer(&mut network, 10 + i, neighbor, true, now - 1000);
+        }
+
+        {
+            let mut tx = network.peerdb.tx_begin().unwrap();
+            PeerDB::try_insert_peer(&mut tx, &org_3_neighbor).unwrap();
+            PeerDB::try_insert_peer(&mut tx, &org_0_neighbor).unwrap();
+            tx.commit().unwrap();
+        }
+
+        network.prune_frontier(&HashSet::new());
+
+        // one org-1 peer was pruned
+        assert_eq!(network.peers.len(), 3);
+        let distribution = network.org_neighbor_distribution(network.peerdb.conn(), &HashSet::new()).unwrap();
+        assert_eq!(distribution.get(&1).unwrap().len(), 2);
+        assert_eq!(distribution.get(&2).unwrap().len(), 1);
+        assert!(distribution.get(&3).is_none());
+
+        assert_eq!(network.under_represented_orgs, vec![2, 3]);
+
+        // new walks start from the under-represented orgs
+        let neighbors = network.get_random_neighbors(1, network.chain_view.burn_block_height).unwrap();
+        assert_eq!(neighbors.len(), 1);
+        assert!(neighbors[0].org == 2 || neighbors[0].org == 3);
+
+        // ...but only half of them; the rest are the usual random walk neighbors
+        let neighbors = network.get_random_neighbors(4, network.chain_view.burn_block_height).unwrap();
+        assert_eq!(neighbors.len(), 4);
+        assert!(neighbors.iter().any(|n| n.org == 2));
+        assert!(neighbors.iter().any(|n| n.org == 3));
+        assert_eq!(neighbors.iter().filter(|n| n.org != 2 && n.org != 3).count(), 2);
+    }
+
+    #[test]
+    fn test_prune_metrics() {
+        let mut network = make_test_prune_network(ConnectionOptions::default());
+        let now = get_epoch_time_secs();
+
+        assert_eq!(*network.get_prune_metrics(), PruneMetrics::default());
+
+        // 20 orgs with 10 outbound neighbors each
+        for org in 1..21 {
+            for j in 0..10 {
+                let neighbor = make_test_prune_neighbor(org as u8, 30000 + j, org);
+                add_test_prune_peer(&mut network, (org as usize) * 100 + (j as usize), &neighbor, true, now - 1000);
+            }
+        }
+
+        // 10 hosts with 20 inbound connections each
+        for i in 0..10 {
+            for j in 0..20 {
+                let neighbor = make_test_prune_neighbor(100 + i, 40000 + j, 0);
+                add_test_prune_peer(&mut network, 10000 + (i as usize) * 100 + (j as usize), &neighbor, false, now - 1000);
+            }
+        }
+
+        network.prune_frontier(&HashSet::new());
+
+        let metrics = network.get_prune_metrics().clone();
+        assert_eq!(metrics.num_passes, 1);
+        assert_eq!(metrics.last_num_outbound, 200);
+        assert_eq!(metrics.last_num_inbound, 200);
+        assert_eq!(metrics.last_inbound_ip_pruned, 100);
+        assert_eq!(metrics.last_outbound_org_pruned, 180);
+        assert_eq!(metrics.total_inbound_ip_duration, metrics.last_inbound_ip_duration);
+        assert_eq!(metrics.total_outbound_org_duration, metrics.last_outbound_org_duration);
+
+        network.prune_frontier(&HashSet::new());
+
+        let next_metrics = network.get_prune_metrics().clone();
+        assert_eq!(next_metrics.num_passes, 2);
+        assert_eq!(next_metrics.last_num_outbound, 20);
+        assert_eq!(next_metrics.last_num_inbound, 100);
+        assert_eq!(next_metrics.last_inbound_ip_pruned, 0);
+        assert_eq!(next_metrics.last_outbound_org_pruned, 0);
+        assert_eq!(next_metrics.total_inbound_ip_duration, metrics.total_inbound_ip_duration + next_metrics.last_inbound_ip_duration);
+        assert_eq!(next_metrics.total_outbound_org_duration, metrics.total_outbound_org_duration + next_metrics.last_outbound_org_duration);
+        assert!(next_metrics.total_inbound_ip_duration >= metrics.total_inbound_ip_duration);
+        assert!(next_metrics.total_outbound_org_duration >= metrics.total_outbound_org_duration);
+    }
+
+    #[test]
+    fn test_neighbor_prune_inputs() {
+        let mut network = make_test_prune_network(ConnectionOptions::default());
+        let now = get_epoch_time_secs();
+
+        let neighbor = make_test_prune_neighbor(1, 20001, 123);
+        add_test_prune_peer(&mut network, 1, &neighbor, true, now - 1000);
+
+        let inbound_neighbor = make_test_prune_neighbor(2, 20002, 456);
+        add_test_prune_peer(&mut network, 2, &inbound_neighbor, false, now - 10);
+
+        {
+            // 24 recent successes, 4 recent failures, and 4 stale successes
+            let stats = &mut network.peers.get_mut(&1).unwrap().stats;
+            for i in 0..NUM_HEALTH_POINTS {
+                let hp = NeighborHealthPoint {
+                    success: i < 24 || i >= 28,
+                    time: if i >= 28 { now - HEALTH_POINT_LIFETIME - 1 } else { now }
+                };
+                stats.healthpoints.push_back(hp);
+            }
+            stats.msg_rx_counts.insert(StacksMessageID::Blocks, 2);
+            stats.msg_rx_counts.insert(StacksMessageID::Transaction, 3);
+            stats.msg_rx_counts.insert(StacksMessageID::Ping, 100);
+        }
+
+        let inputs = network.neighbor_prune_inputs(&neighbor.addr).unwrap();
+        let stats = network.get_neighbor_stats(&neighbor.addr).unwrap();
+
+        assert!(inputs.outbound);
+        assert_eq!(inputs.org, Some(123));
+        assert_eq!(inputs.uptime_bucket, PeerNetwork::uptime_bucket(&stats, get_epoch_time_secs(), 2.0));
+        assert_eq!(inputs.uptime_bucket, 10);
+        assert_eq!(inputs.health_score, stats.get_health_score());
+        assert_eq!(inputs.health_score, 24.0 / 32.0);
+        assert_eq!(inputs.raw_health_score, 28.0 / 32.0);
+        assert_eq!(inputs.relay_value, 5);
+
+        let inbound_inputs = network.neighbor_prune_inputs(&inbound_neighbor.addr).unwrap();
+        assert!(!inbound_inputs.outbound);
+        assert_eq!(inbound_inputs.org, Some(456));
+        assert_eq!(inbound_inputs.uptime_bucket, 3);
+        assert_eq!(inbound_inputs.health_score, 0.5);
+        assert_eq!(inbound_inputs.relay_value, 0);
+
+        let not_connected = make_test_prune_neighbor(3, 20003, 123);
+        assert!(network.neighbor_prune_inputs(&not_connected.addr).is_none());
+    }
+
+    #[test]
+    fn test_prune_count_decay_and_reset() {
+        let mut network = make_test_prune_network(ConnectionOptions::default());
+        let interval = network.connection_opts.prune.count_decay_interval;
+        let now = get_epoch_time_secs();
+
+        let stale = make_test_prune_neighbor(1, 20001, 1).addr;
+        let recent = make_test_prune_neighbor(2, 20002, 1).addr;
+        let inbound = make_test_prune_neighbor(3, 20003, 1).addr;
+
+        for _ in 0..4 {
+            PeerNetwork::record_prune(&mut network.prune_outbound_counts, &mut network.prune_outbound_times, &stale, now - interval);
+            PeerNetwork::record_prune(&mut network.prune_outbound_counts, &mut network.prune_outbound_times, &recent, now);
+            PeerNetwork::record_prune(&mut network.prune_inbound_counts, &mut network.prune_inbound_times, &inbound, now - interval);
+        }
+
+        // stale counts get halved; recent ones don't
+        network.decay_prune_counts(now);
+        assert_eq!(network.prune_outbound_counts.get(&stale), Some(&2));
+        assert_eq!(network.prune_outbound_counts.get(&recent), Some(&4));
+        assert_eq!(network.prune_inbound_counts.get(&inbound), Some(&2));
+
+        // decay happens at most once per interval
+        network.decay_prune_counts(now);
+        assert_eq!(network.prune_outbound_counts.get(&stale), Some(&2));
+
+        // and eventually forgets the peer entirely
+        network.decay_prune_counts(now + interval);
+        assert_eq!(network.prune_outbound_counts.get(&stale), Some(&1));
+        assert_eq!(network.prune_outbound_counts.get(&recent), Some(&2));
+        network.decay_prune_counts(now + 2 * interval);
+        assert_eq!(network.prune_outbound_counts.get(&stale), None);
+        assert_eq!(network.prune_outbound_times.get(&stale), None);
+        assert_eq!(network.prune_outbound_counts.get(&recent), Some(&1));
+
+        // reset clears only the given neighbor, in both directions
+        PeerNetwork::record_prune(&mut network.prune_inbound_counts, &mut network.prune_inbound_times, &recent, now);
+        PeerNetwork::record_prune(&mut network.prune_inbound_counts, &mut network.prune_inbound_times, &inbound, now);
+
+        network.reset_prune_counts(&recent);
+        assert_eq!(network.prune_outbound_counts.get(&recent), None);
+        assert_eq!(network.prune_outbound_times.get(&recent), None);
+        assert_eq!(network.prune_inbound_counts.get(&recent), None);
+        assert_eq!(network.prune_inbound_times.get(&recent), None);
+        assert_eq!(network.prune_inbound_counts.get(&inbound), Some(&1));
+    }
+
+    #[test]
+    fn test_prune_prefer_newer_versions() {
+        let now = get_epoch_time_secs();
+
+        // the newer peer has the lower key, so the key tie-break alone would prune it first
+        let newer = make_test_prune_neighbor(1, 20001, 1);
+        let older = make_test_prune_neighbor(2, 20001, 1);
+
+        for prefer_newer_versions in [true, false].iter() {
+            let mut conn_opts = ConnectionOptions::default();
+            conn_opts.prune.soft_num_neighbors = 1;
+            conn_opts.prune.soft_max_neighbors_per_org = 1;
+            conn_opts.prune.prefer_newer_versions = *prefer_newer_versions;
+
+            let mut network = make_test_prune_network(conn_opts);
+            add_test_prune_peer(&mut network, 1, &newer, true, now - 1000);
+            add_test_prune_peer(&mut network, 2, &older, true, now - 1000);
+
+            network.peers.get_mut(&1).unwrap().stats.peer_version = 0x12345679;
+            network.peers.get_mut(&2).unwrap().stats.peer_version = 0x12345678;
+
+            network.prune_frontier(&HashSet::new());
+
+            if *prefer_newer_versions {
+                assert!(network.is_registered(&newer.addr));
+                assert!(!network.is_registered(&older.addr));
+            }
+            else {
+                assert!(!network.is_registered(&newer.addr));
+                assert!(network.is_registered(&older.addr));
+            }
+        }
+    }
+
+    #[test]
+    fn test_prune_spares_sole_org_peer() {
+        let now = get_epoch_time_secs();
+
+        let sole = make_test_prune_neighbor(1, 20001, 1);
+        let first = make_test_prune_neighbor(2, 20001, 2);
+        let second = make_test_prune_neighbor(3, 20001, 2);
+
+        // with no per-org allowance, the org pruner would remove every peer it can
+        let mut conn_opts = ConnectionOptions::default();
+        conn_opts.prune.soft_num_neighbors = 1;
+        conn_opts.prune.soft_max_neighbors_per_org = 0;
+
+        let mut network = make_test_prune_network(conn_opts.clone());
+        add_test_prune_peer(&mut network, 1, &sole, true, now - 1000);
+        add_test_prune_peer(&mut network, 2, &first, true, now - 1000);
+        add_test_prune_peer(&mut network, 3, &second, true, now - 1000);
+
+        network.prune_frontier(&HashSet::new());
+
+        // the only peer in org 1 and the last peer in org 2 are spared
+        assert!(network.is_registered(&sole.addr));
+        assert!(!network.is_registered(&first.addr));
+        assert!(network.is_registered(&second.addr));
+        assert_eq!(network.get_prune_metrics().last_outbound_org_pruned, 1);
+
+        // over the hard cap, the valve doesn't apply
+        conn_opts.num_neighbors = 2;
+
+        let mut network = make_test_prune_network(conn_opts);
+        add_test_prune_peer(&mut network, 1, &sole, true, now - 1000);
+        add_test_prune_peer(&mut network, 2, &first, true, now - 1000);
+        add_test_prune_peer(&mut network, 3, &second, true, now - 1000);
+
+        network.prune_frontier(&HashSet::new());
+
+        assert_eq!(network.get_prune_metrics().last_outbound_org_pruned, 2);
+        assert_eq!(PeerNetwork::count_outbound_conversations(&network.peers), 1);
+    }
+
+    #[test]
+    fn test_prune_keeps_reserved_outbound_slots() {
+        let now = get_epoch_time_secs();
+
+        let mut conn_opts = ConnectionOptions::default();
+        conn_opts.prune.soft_num_clients = 4;
+        conn_opts.prune.soft_num_neighbors = 2;
+        conn_opts.prune.reserved_outbound_slots = 3;
+
+        // 6 inbound peers from distinct hosts (so none get pruned by IP) and 1 outbound peer
+        let mut network = make_test_prune_network(conn_opts.clone());
+        let mut inbound = vec![];
+        for i in 0..6 {
+            let neighbor = make_test_prune_neighbor(10 + i, 30000, 1);
+            add_test_prune_peer(&mut network, 10 + (i as usize), &neighbor, false, now - 1000 + (i as u64));
+            inbound.push(neighbor);
+        }
+        let outbound = make_test_prune_neighbor(1, 20001, 2);
+        add_test_prune_peer(&mut network, 1, &outbound, true, now - 1000);
+
+        network.prune_frontier(&HashSet::new());
+
+        // 6 slots, 3 of which are reserved for outbound peers.  The newest inbound peers go.
+        assert_eq!(network.get_prune_metrics().last_inbound_reserved_pruned, 3);
+        for i in 0..3 {
+            assert!(network.is_registered(&inbound[i].addr));
+        }
+        for i in 3..6 {
+            assert!(!network.is_registered(&inbound[i].addr));
+        }
+        assert!(network.is_registered(&outbound.addr));
+        assert_eq!(network.peers.len(), 4);
+
+        // without a reservation, this pass is off
+        conn_opts.prune.reserved_outbound_slots = 0;
+
+        let mut network = make_test_prune_network(conn_opts);
+        for i in 0..6 {
+            add_test_prune_peer(&mut network, 10 + (i as usize), &inbound[i], false, now - 1000 + (i as u64));
+        }
+        add_test_prune_peer(&mut network, 1, &outbound, true, now - 1000);
+
+        network.prune_frontier(&HashSet::new());
+
+        assert_eq!(network.get_prune_metrics().last_inbound_reserved_pruned, 0);
+        assert_eq!(network.peers.len(), 7);
+    }
+
+    #[test]
+    fn test_prune_reserved_outbound_slots_after_outbound_prune() {
+        let now = get_epoch_time_secs();
+
+        let mut conn_opts = ConnectionOptions::default();
+        conn_opts.prune.soft_num_clients = 4;
+        conn_opts.prune.soft_num_neighbors = 2;
+        conn_opts.prune.soft_max_neighbors_per_org = 1;
+        conn_opts.prune.reserved_outbound_slots = 1;
+
+        // 5 inbound peers from distinct hosts and 4 outbound peers, 2 more than we want
+        let mut network = make_test_prune_network(conn_opts);
+        let mut inbound = vec![];
+        for i in 0..5 {
+            let neighbor = make_test_prune_neighbor(10 + i, 30000, 1);
+            add_test_prune_peer(&mut network, 10 + (i as usize), &neighbor, false, now - 1000 + (i as u64));
+            inbound.push(neighbor);
+        }
+        for i in 0..4 {
+            let neighbor = make_test_prune_neighbor(1 + i, 20001, 2);
+            add_test_prune_peer(&mut network, 1 + (i as usize), &neighbor, true, now - 1000);
+        }
+
+        network.prune_frontier(&HashSet::new());
+
+        // the excess outbound peers don't count against the inbound peers' slots: of the 6 slots,
+        // the 2 outbound peers we kept take 2, leaving 4 for inbound peers
+        assert_eq!(network.get_prune_metrics().last_outbound_org_pruned, 2);
+        assert_eq!(network.get_prune_metrics().last_inbound_reserved_pruned, 1);
+        assert!(!network.is_registered(&inbound[4].addr));
+        assert_eq!(network.peers.len(), 6);
+    }
+
+    #[test]
+    fn test_prune_sim_ip_and_org() {
+        let mut conn_opts = ConnectionOptions::default();
+        conn_opts.prune.soft_num_clients = 3;
+        conn_opts.prune.soft_max_clients_per_host = 1;
+        conn_opts.prune.soft_num_neighbors = 2;
+        conn_opts.prune.soft_max_neighbors_per_org = 1;
+        conn_opts.prune.reserved_outbound_slots = 0;
+
+        let mut sim = PeerNetworkPruneSim::new(conn_opts);
+
+        // four inbound peers behind one IP; only the oldest survives the IP pass
+        let shared_ip = PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x01,0x01]);
+        let inbound : Vec<NeighborKey> = (0..4).map(|i| sim.add_peer(shared_ip.clone(), 40001 + i, 100, false, 1000 - (i as u64))).collect();
+
+        // three outbound peers in org 1 and one in org 2; org 1 loses its young, unhealthy peers
+        let old_peer = sim.add_peer(PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x02,0x01]), 20001, 1, true, 10000);
+        let young_unhealthy = sim.add_peer(PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x02,0x02]), 20001, 1, true, 100);
+        let young_healthy = sim.add_peer(PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x02,0x03]), 20001, 1, true, 100);
+        let other_org = sim.add_peer(PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x03,0x01]), 20001, 2, true, 100);
+
+        sim.set_health_score(&young_unhealthy, 0.2);
+        sim.set_health_score(&young_healthy, 0.9);
+
+        let pruned = sim.prune();
+        assert_eq!(pruned, vec![inbound[1].clone(), inbound[2].clone(), inbound[3].clone(), young_unhealthy.clone(), young_healthy.clone()]);
+
+        assert!(sim.is_connected(&inbound[0]));
+        assert!(sim.is_connected(&old_peer));
+        assert!(sim.is_connected(&other_org));
+
+        let metrics = sim.network.get_prune_metrics();
+        assert_eq!(metrics.last_inbound_ip_pruned, 3);
+        assert_eq!(metrics.last_inbound_reserved_pruned, 0);
+        assert_eq!(metrics.last_outbound_org_pruned, 2);
+    }
+
+    #[test]
+    fn test_prune_spares_allowlisted_peers() {
+        let mut conn_opts = ConnectionOptions::default();
+        conn_opts.prune.soft_num_clients = 1;
+        conn_opts.prune.soft_max_clients_per_host = 1;
+        conn_opts.prune.soft_num_neighbors = 1;
+        conn_opts.prune.soft_max_neighbors_per_org = 1;
+        conn_opts.prune.reserved_outbound_slots = 0;
+
+        let mut sim = PeerNetworkPruneSim::new(conn_opts);
+
+        // the newest inbound peer from a crowded IP would be the first to go
+        let shared_ip = PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x01,0x01]);
+        let old_inbound = sim.add_peer(shared_ip.clone(), 40001, 100, false, 1000);
+        let new_inbound = sim.add_peer(shared_ip.clone(), 40002, 100, false, 10);
+
+        // the young, unhealthy outbound peer in a crowded org would be the first to go
+        let old_outbound = sim.add_peer(PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x02,0x01]), 20001, 1, true, 10000);
+        let new_outbound = sim.add_peer(PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x02,0x02]), 20001, 1, true, 10);
+        sim.set_health_score(&new_outbound, 0.0);
+
+        sim.network.connection_opts.allowlist.push(NeighborMatcher::Exact(new_inbound.clone()));
+        sim.network.connection_opts.allowlist.push(NeighborMatcher::Exact(new_outbound.clone()));
+
+        let pruned = sim.prune();
+        assert_eq!(pruned, vec![old_inbound.clone(), old_outbound.clone()]);
+
+        assert!(sim.is_connected(&new_inbound));
+        assert!(sim.is_connected(&new_outbound));
+    }
+
+    #[test]
+    fn test_prune_spares_allowlisted_subnets() {
+        let mut conn_opts = ConnectionOptions::default();
+        conn_opts.prune.soft_num_clients = 1;
+        conn_opts.prune.soft_max_clients_per_host = 1;
+
+        // the newest inbound peer from a crowded IP would be the first to go
+        let shared_ip = PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x01,0x01]);
+
+        // 10.0.1.0/24, on our network or another one
+        for network_id in [0x9abcdef0, 0x9abcdef1].iter() {
+            let mut sim = PeerNetworkPruneSim::new(conn_opts.clone());
+            let old_inbound = sim.add_peer(shared_ip.clone(), 40001, 100, false, 1000);
+            let new_inbound = sim.add_peer(shared_ip.clone(), 40002, 100, false, 10);
+
+            sim.network.connection_opts.allowlist.push(NeighborMatcher::Subnet {
+                addr: PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x01,0x00]),
+                prefix_len: 120,
+                network_id: Some(*network_id)
+            });
+
+            let pruned = sim.prune();
+            if *network_id == sim.network.local_peer.network_id {
+                assert_eq!(pruned, vec![]);
+            }
+            else {
+                assert_eq!(pruned, vec![new_inbound.clone()]);
+            }
+            assert!(sim.is_connected(&old_inbound));
+        }
+    }
+
+    #[test]
+    fn test_prune_drops_denylisted_peers() {
+        let mut sim = PeerNetworkPruneSim::new(ConnectionOptions::default());
+
+        let exact = sim.add_peer(PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x01,0x01]), 20001, 1, true, 10000);
+        let in_subnet_1 = sim.add_peer(PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x02,0x01]), 20001, 2, false, 10000);
+        let in_subnet_2 = sim.add_peer(PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x02,0x7f]), 20001, 3, true, 10000);
+        let out_of_subnet = sim.add_peer(PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x02,0x80]), 20001, 4, true, 10000);
+
+        sim.network.connection_opts.denylist.push(NeighborMatcher::Exact(exact.clone()));
+
+        // 10.0.2.0/25
+        sim.network.connection_opts.denylist.push(NeighborMatcher::Subnet {
+            addr: PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x02,0x00]),
+            prefix_len: 121,
+            network_id: None
+        });
+
+        // neither preserving nor allowlisting a denylisted peer saves it
+        sim.network.connection_opts.allowlist.push(NeighborMatcher::Exact(in_subnet_2.clone()));
+
+        let mut preserve = HashSet::new();
+        for nk in [&exact, &in_subnet_1, &in_subnet_2, &out_of_subnet].iter() {
+            preserve.insert(*sim.network.events.get(*nk).unwrap());
+        }
+
+        sim.network.prune_frontier(&preserve);
+
+        assert!(!sim.is_connected(&exact));
+        assert!(!sim.is_connected(&in_subnet_1));
+        assert!(!sim.is_connected(&in_subnet_2));
+        assert!(sim.is_connected(&out_of_subnet));
+        assert_eq!(sim.network.get_prune_metrics().last_denylist_pruned, 3);
+    }
+
+    #[test]
+    fn test_prune_spares_walking_neighbors() {
+        let mut conn_opts = ConnectionOptions::default();
+        conn_opts.prune.soft_num_neighbors = 1;
+        conn_opts.prune.soft_max_neighbors_per_org = 1;
+        conn_opts.prune.reserved_outbound_slots = 0;
+
+        let mut sim = PeerNetworkPruneSim::new(conn_opts);
+
+        // the young, unhealthy outbound peer in a crowded org would be the first to go
+        let old_outbound = sim.add_peer(PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x02,0x01]), 20001, 1, true, 10000);
+        let walking = sim.add_peer(PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x02,0x02]), 20001, 1, true, 10);
+        sim.set_health_score(&walking, 0.0);
+
+        // we're waiting on a GetNeighbors reply from it
+        sim.network.walking_neighbors.insert(walking.clone());
+
+        let pruned = sim.prune();
+        assert_eq!(pruned, vec![old_outbound.clone()]);
+        assert!(sim.is_connected(&walking));
+
+        // the query resolved (there's no walk in progress), so it's fair game again
+        let other = sim.add_peer(PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x02,0x03]), 20001, 1, true, 10000);
+        sim.network.update_walking_neighbors();
+        assert!(sim.network.walking_neighbors.is_empty());
+
+        let pruned = sim.prune();
+        assert_eq!(pruned, vec![walking.clone()]);
+        assert!(sim.is_connected(&other));
+    }
+
+    #[test]
+    fn test_prune_ignores_remote_disconnects() {
+        let now = get_epoch_time_secs();
+        let mut sim = PeerNetworkPruneSim::new(ConnectionOptions::default());
+
+        let gone_inbound = sim.add_peer(PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x01,0x01]), 40001, 1, false, 1000);
+        let gone_outbound = sim.add_peer(PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x02,0x01]), 20001, 2, true, 1000);
+        let live_inbound = sim.add_peer(PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x01,0x02]), 40001, 1, false, 1000);
+
+        // the remote peers close their connections after a prune pass chose them, but before we
+        // got around to disconnecting them
+        assert!(sim.network.deregister_neighbor(&gone_inbound));
+        assert!(sim.network.deregister_neighbor(&gone_outbound));
+        assert!(!sim.network.deregister_neighbor(&gone_inbound));
+        assert!(!sim.network.deregister_neighbor(&gone_outbound));
+
+        assert!(!sim.network.prune_neighbor(&gone_inbound, false, now));
+        assert!(!sim.network.prune_neighbor(&gone_outbound, true, now));
+        assert!(sim.network.prune_inbound_counts.get(&gone_inbound).is_none());
+        assert!(sim.network.prune_outbound_counts.get(&gone_outbound).is_none());
+
+        // we still count the prunes we do
+        assert!(sim.network.prune_neighbor(&live_inbound, false, now));
+        assert!(!sim.is_connected(&live_inbound));
+        assert_eq!(sim.network.prune_inbound_counts.get(&live_inbound), Some(&1));
+
+        assert!(!sim.network.prune_neighbor(&live_inbound, false, now));
+        assert_eq!(sim.network.prune_inbound_counts.get(&live_inbound), Some(&1));
+    }
+
+    #[test]
+    fn test_top_pruned_orgs() {
+        let mut conn_opts = ConnectionOptions::default();
+        conn_opts.prune.soft_num_neighbors = 4;
+        conn_opts.prune.soft_max_neighbors_per_org = 2;
+
+        let mut sim = PeerNetworkPruneSim::new(conn_opts);
+        assert!(sim.network.top_pruned_orgs(3).is_empty());
+
+        // org 1 dominates, org 2 is a little over its allowance, and org 3 is within it
+        for cycle in 0..5u8 {
+            for (org, count) in [(1u32, 8u8), (2, 3), (3, 2)].iter() {
+                for i in 0..*count {
+                    sim.add_peer(PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,*org as u8,cycle,i]), 20001, *org, true, 1000);
+                }
+            }
+            sim.prune();
+        }
+
+        let top = sim.network.top_pruned_orgs(3);
+        assert_eq!(top[0].0, 1);
+        for i in 1..top.len() {
+            assert!(top[i - 1].1 >= top[i].1);
+        }
+        assert_eq!(sim.network.top_pruned_orgs(1), vec![top[0]]);
+        assert_eq!(sim.network.top_pruned_orgs(0), vec![]);
+    }
+
+    #[test]
+    fn test_prune_spares_new_peers() {
+        let mut conn_opts = ConnectionOptions::default();
+        conn_opts.prune.soft_num_neighbors = 2;
+        conn_opts.prune.soft_max_neighbors_per_org = 1;
+        conn_opts.prune.min_age_before_prune_secs = 60;
+
+        let mut sim = PeerNetworkPruneSim::new(conn_opts);
+
+        // org 1 is over-represented, and its young, unhealthy peer would be the first to go.  The
+        // other peers are allowlisted, so the new peer is the only one the org pruner could take.
+        let old_peer = sim.add_peer(PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x02,0x01]), 20001, 1, true, 10000);
+        let new_peer = sim.add_peer(PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x02,0x02]), 20001, 1, true, 10);
+        let other_org_peer = sim.add_peer(PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x03,0x01]), 20001, 2, true, 10000);
+        sim.set_health_score(&new_peer, 0.0);
+
+        sim.network.connection_opts.allowlist.push(NeighborMatcher::Exact(old_peer.clone()));
+        sim.network.connection_opts.allowlist.push(NeighborMatcher::Exact(other_org_peer.clone()));
+
+        assert_eq!(sim.prune(), vec![]);
+        assert!(sim.is_connected(&new_peer));
+
+        // once it's been around long enough, it's fair game
+        let event_id = *sim.network.events.get(&new_peer).unwrap();
+        sim.network.peers.get_mut(&event_id).unwrap().stats.first_contact_time = get_epoch_time_secs() - 61;
+
+        assert_eq!(sim.prune(), vec![new_peer.clone()]);
+        assert!(sim.is_connected(&old_peer));
+        assert!(sim.is_connected(&other_org_peer));
+    }
+
+    #[test]
+    fn test_prune_new_peers_over_hard_limit() {
+        let mut conn_opts = ConnectionOptions::default();
+        conn_opts.num_neighbors = 2;
+        conn_opts.prune.soft_num_neighbors = 2;
+        conn_opts.prune.soft_max_neighbors_per_org = 1;
+        conn_opts.prune.min_age_before_prune_secs = 60;
+
+        let mut sim = PeerNetworkPruneSim::new(conn_opts);
+
+        let old_peer = sim.add_peer(PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x02,0x01]), 20001, 1, true, 10000);
+        let new_peer = sim.add_peer(PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x02,0x02]), 20001, 1, true, 10);
+        let other_org_peer = sim.add_peer(PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x03,0x01]), 20001, 2, true, 10000);
+        sim.set_health_score(&new_peer, 0.0);
+
+        sim.network.connection_opts.allowlist.push(NeighborMatcher::Exact(old_peer.clone()));
+        sim.network.connection_opts.allowlist.push(NeighborMatcher::Exact(other_org_peer.clone()));
+
+        // we're over num_neighbors, so even a brand-new peer can go
+        assert_eq!(sim.prune(), vec![new_peer.clone()]);
+    }
+
+    #[test]
+    fn test_prune_replay_matches_live_prune() {
+        let mut conn_opts = ConnectionOptions::default();
+        conn_opts.prune.soft_num_clients = 2;
+        conn_opts.prune.soft_max_clients_per_host = 1;
+        conn_opts.prune.soft_num_neighbors = 2;
+        conn_opts.prune.soft_max_neighbors_per_org = 3;
+        conn_opts.prune.reserved_outbound_slots = 0;
+
+        let mut sim = PeerNetworkPruneSim::new(conn_opts);
+
+        // three inbound peers behind one IP, and an allowlisted one elsewhere
+        let shared_ip = PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x01,0x01]);
+        for i in 0..3 {
+            sim.add_peer(shared_ip.clone(), 40001 + i, 100, false, 1000 * (i as u64 + 1));
+        }
+        let allowed = sim.add_peer(PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x01,0x02]), 40001, 100, false, 1000);
+        sim.network.connection_opts.allowlist.push(NeighborMatcher::Exact(allowed.clone()));
+
+        // lopsided orgs, so the org pass has to fall back to sampling orgs at random
+        let mut outbound = vec![];
+        for i in 0..4 {
+            outbound.push(sim.add_peer(PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x02,0x01 + i]), 20001, 1, true, 1000 * (i as u64 + 1)));
+        }
+        for i in 0..2 {
+            outbound.push(sim.add_peer(PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x03,0x01 + i]), 20001, 2, true, 20000));
+        }
+        outbound.push(sim.add_peer(PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x04,0x01]), 20001, 3, true, 5000));
+        sim.set_health_score(&outbound[1], 0.3);
+        sim.set_health_score(&outbound[4], 0.8);
+
+        let denied = sim.add_peer(PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x05,0x01]), 20001, 4, true, 20000);
+        sim.network.connection_opts.denylist.push(NeighborMatcher::Exact(denied.clone()));
+
+        sim.network.prune_rng_seed = Some(12345);
+
+        let snapshot = sim.network.peer_table_snapshot(&HashSet::new()).unwrap();
+        assert_eq!(snapshot.entries.len(), 12);
+        assert_eq!(snapshot.entries.iter().filter(|e| e.preserved).count(), 1);
+        assert_eq!(snapshot.entries.iter().filter(|e| e.denied).count(), 1);
+
+        let snapshot_json = serde_json::to_string(&snapshot).unwrap();
+        let snapshot : PeerTableSnapshot = serde_json::from_str(&snapshot_json).unwrap();
+
+        let thresholds = sim.network.connection_opts.prune.clone();
+        let replayed = prune_replay(&snapshot, &thresholds, 12345).unwrap();
+        let live = sim.network.prune_frontier_outcome(&HashSet::new());
+
+        assert_eq!(replayed, live);
+
+        assert!(live.pruned_outbound.contains(&denied));
+        assert!(live.pruned_outbound.len() > 1);
+        assert!(live.pruned_inbound.len() > 0);
+        assert!(!live.pruned_inbound.contains(&allowed));
+    }
+
+    #[test]
+    fn test_prune_replay_during_ibd() {
+        let mut conn_opts = ConnectionOptions::default();
+        conn_opts.num_neighbors = 4;
+        conn_opts.prune.soft_num_neighbors = 2;
+        conn_opts.prune.soft_max_neighbors_per_org = 1;
+
+        let mut sim = PeerNetworkPruneSim::new(conn_opts);
+        sim.network.in_ibd = true;
+        sim.network.prune_rng_seed = Some(12345);
+
+        for i in 0..6 {
+            let addrbytes = PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x0c,(i + 1) as u8]);
+            sim.add_peer(addrbytes, 20001, 1, true, 1000 * (i as u64 + 1));
+        }
+
+        let snapshot = sim.network.peer_table_snapshot(&HashSet::new()).unwrap();
+        assert!(snapshot.in_ibd);
+        assert_eq!(snapshot.num_neighbors, 4);
+
+        let snapshot_json = serde_json::to_string(&snapshot).unwrap();
+        let snapshot : PeerTableSnapshot = serde_json::from_str(&snapshot_json).unwrap();
+
+        let thresholds = sim.network.connection_opts.prune.clone();
+        let replayed = prune_replay(&snapshot, &thresholds, 12345).unwrap();
+        let live = sim.network.prune_frontier_outcome(&HashSet::new());
+
+        // only down to the hard limit
+        assert_eq!(live.pruned_outbound.len(), 2);
+        assert_eq!(replayed, live);
+
+        // out of IBD, the same table is pruned down to soft_num_neighbors
+        let mut snapshot = snapshot;
+        snapshot.in_ibd = false;
+        assert_eq!(prune_replay(&snapshot, &thresholds, 12345).unwrap().pruned_outbound.len(), 4);
+    }
+
+    #[test]
+    fn test_prune_prefers_low_latency_peers() {
+        let mut conn_opts = ConnectionOptions::default();
+        conn_opts.prune.soft_num_neighbors = 1;
+        conn_opts.prune.soft_max_neighbors_per_org = 1;
+        conn_opts.prune.latency_tier_ms = 100;
+
+        let mut sim = PeerNetworkPruneSim::new(conn_opts);
+
+        // same org, uptime, and health; the fast peer has the lower key, so it would go first
+        // if latency didn't count
+        let fast_peer = sim.add_peer(PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x02,0x01]), 20001, 1, true, 1000);
+        let slow_peer = sim.add_peer(PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x02,0x02]), 20001, 1, true, 1000);
+        sim.set_rtt(&fast_peer, 20.0);
+        sim.set_rtt(&slow_peer, 300.0);
+
+        assert_eq!(sim.prune(), vec![slow_peer.clone()]);
+        assert!(sim.is_connected(&fast_peer));
+    }
+
+    #[test]
+    fn test_prune_protects_archival_peers() {
+        let mut conn_opts = ConnectionOptions::default();
+        conn_opts.prune.soft_num_neighbors = 1;
+        conn_opts.prune.soft_max_neighbors_per_org = 1;
+        conn_opts.prune.protected_services = ServiceFlags::ARCHIVAL as u16;
+
+        let mut sim = PeerNetworkPruneSim::new(conn_opts);
+
+        // same org; the archival peer is newer, less healthy, and has the lower key, so it would
+        // go first if it weren't protected
+        let archival_peer = sim.add_peer(PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x03,0x01]), 20001, 1, true, 100);
+        let plain_peer = sim.add_peer(PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x03,0x02]), 20001, 1, true, 100000);
+        sim.set_health_score(&archival_peer, 0.5);
+        sim.set_health_score(&plain_peer, 1.0);
+        sim.set_services(&archival_peer, (ServiceFlags::RELAY as u16) | (ServiceFlags::ARCHIVAL as u16));
+        sim.set_services(&plain_peer, ServiceFlags::RELAY as u16);
+
+        assert_eq!(sim.prune(), vec![plain_peer.clone()]);
+        assert!(sim.is_connected(&archival_peer));
+    }
+
+    #[test]
+    fn test_prune_protected_peers_without_alternative() {
+        let mut conn_opts = ConnectionOptions::default();
+        conn_opts.prune.soft_num_neighbors = 1;
+        conn_opts.prune.soft_max_neighbors_per_org = 1;
+        conn_opts.prune.protected_services = ServiceFlags::ARCHIVAL as u16;
+
+        let mut sim = PeerNetworkPruneSim::new(conn_opts);
+
+        // every peer in the org is protected, so the usual order decides
+        let new_peer = sim.add_peer(PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x04,0x01]), 20001, 1, true, 100);
+        let old_peer = sim.add_peer(PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x04,0x02]), 20001, 1, true, 100000);
+        sim.set_services(&new_peer, ServiceFlags::ARCHIVAL as u16);
+        sim.set_services(&old_peer, ServiceFlags::ARCHIVAL as u16);
+
+        assert_eq!(sim.prune(), vec![new_peer.clone()]);
+        assert!(sim.is_connected(&old_peer));
+    }
+
+    #[test]
+    fn test_prune_prefers_useful_inbound_peers() {
+        let shared_ip = PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x05,0x01]);
+
+        for prefer_useful_inbound in [true, false].iter() {
+            let mut conn_opts = ConnectionOptions::default();
+            conn_opts.prune.soft_num_clients = 1;
+            conn_opts.prune.soft_max_clients_per_host = 1;
+            conn_opts.prune.prefer_useful_inbound = *prefer_useful_inbound;
+
+            let mut sim = PeerNetworkPruneSim::new(conn_opts);
+
+            // same IP; the idle peer is older, so it's the one kept if usefulness doesn't count
+            let idle_peer = sim.add_peer(shared_ip.clone(), 40001, 100, false, 100000);
+            let useful_peer = sim.add_peer(shared_ip.clone(), 40002, 100, false, 100);
+            sim.set_relay_value(&useful_peer, 10);
+
+            if *prefer_useful_inbound {
+                assert_eq!(sim.prune(), vec![idle_peer.clone()]);
+                assert!(sim.is_connected(&useful_peer));
+            }
+            else {
+                assert_eq!(sim.prune(), vec![useful_peer.clone()]);
+                assert!(sim.is_connected(&idle_peer));
+            }
+        }
+    }
+
+    #[test]
+    fn test_prune_during_ibd() {
+        let mut conn_opts = ConnectionOptions::default();
+        conn_opts.num_neighbors = 4;
+        conn_opts.prune.soft_num_neighbors = 2;
+        conn_opts.prune.soft_max_neighbors_per_org = 1;
+
+        let mut sim = PeerNetworkPruneSim::new(conn_opts);
+        sim.network.in_ibd = true;
+
+        // org 1 dominates, and its newest peer is the one feeding us blocks
+        for i in 0..3 {
+            let addrbytes = PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x09,(i + 1) as u8]);
+            sim.add_peer(addrbytes, 20001, 1, true, 100000);
+        }
+        let serving_peer = sim.add_peer(PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x09,0x10]), 20001, 1, true, 100);
+        sim.set_relay_value(&serving_peer, 1000);
+
+        // at the hard cap, nothing is pruned for org balance
+        assert_eq!(sim.prune(), vec![]);
+        assert!(sim.is_connected(&serving_peer));
+
+        // over the hard cap, we still prune down to it
+        sim.add_peer(PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x09,0x20]), 20001, 2, true, 100000);
+        assert_eq!(sim.prune().len(), 1);
+        assert_eq!(PeerNetwork::count_outbound_conversations(&sim.network.peers), 4);
+
+        // once IBD is done, org balancing resumes
+        sim.network.in_ibd = false;
+        sim.prune();
+        assert_eq!(PeerNetwork::count_outbound_conversations(&sim.network.peers), 2);
+    }
+
+    #[test]
+    fn test_prune_cooldown() {
+        let mut conn_opts = ConnectionOptions::default();
+        conn_opts.prune.soft_num_neighbors = 1;
+        conn_opts.prune.soft_max_neighbors_per_org = 1;
+
+        let mut sim = PeerNetworkPruneSim::new(conn_opts);
+        let old_peer = sim.add_peer(PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x0a,0x01]), 20001, 1, true, 100000);
+        let new_peer = sim.add_peer(PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x0a,0x02]), 20001, 1, true, 100);
+
+        assert_eq!(sim.prune(), vec![new_peer.clone()]);
+
+        // the pruned peer won't be walked to or reconnected to while it cools down
+        let block_height = sim.network.chain_view.burn_block_height;
+        let walk_neighbors = PeerDB::get_random_walk_neighbors(sim.network.peerdb.conn(), 0x9abcdef0, 10, block_height).unwrap();
+        assert_eq!(walk_neighbors.iter().map(|n| n.addr.clone()).collect::<Vec<NeighborKey>>(), vec![old_peer.clone()]);
+
+        let org_neighbors = PeerDB::get_random_neighbors_in_orgs(sim.network.peerdb.conn(), 0x9abcdef0, &vec![1], 10, block_height).unwrap();
+        assert_eq!(org_neighbors.iter().map(|n| n.addr.clone()).collect::<Vec<NeighborKey>>(), vec![old_peer.clone()]);
+
+        // once the cooldown is up, it's a candidate again
+        {
+            let mut tx = sim.network.peerdb.tx_begin().unwrap();
+            PeerDB::set_prune_cooldown(&mut tx, 0x9abcdef0, &new_peer.addrbytes, new_peer.port, get_epoch_time_secs() - 1).unwrap();
+            tx.commit().unwrap();
+        }
+
+        let mut walk_neighbors : Vec<NeighborKey> = PeerDB::get_random_walk_neighbors(sim.network.peerdb.conn(), 0x9abcdef0, 10, block_height).unwrap()
+            .into_iter().map(|n| n.addr).collect();
+        walk_neighbors.sort_by(|nk1, nk2| (&nk1.addrbytes, nk1.port).cmp(&(&nk2.addrbytes, nk2.port)));
+        assert_eq!(walk_neighbors, vec![old_peer.clone(), new_peer.clone()]);
+    }
+
+    #[test]
+    fn test_prune_budget() {
+        let mut conn_opts = ConnectionOptions::default();
+        conn_opts.prune.soft_num_clients = 1;
+        conn_opts.prune.soft_max_clients_per_host = 1;
+        conn_opts.prune.max_prune_per_cycle = 5;
+
+        let mut sim = PeerNetworkPruneSim::new(conn_opts);
+
+        // 20 inbound peers behind one IP, newest last
+        let shared_ip = PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x07,0x01]);
+        let inbound : Vec<NeighborKey> = (0..20).map(|i| sim.add_peer(shared_ip.clone(), 40001 + i, 100, false, 1000 - (i as u64))).collect();
+
+        // the newest go first
+        let pruned = sim.prune();
+        assert_eq!(pruned, inbound[15..20].to_vec());
+
+        let mut num_pruned = pruned.len();
+        for _ in 0..3 {
+            let pruned = sim.prune();
+            assert!(pruned.len() <= 5);
+            num_pruned += pruned.len();
+        }
+        assert_eq!(num_pruned, 19);
+        assert_eq!(sim.prune(), vec![]);
+        assert!(sim.is_connected(&inbound[0]));
+    }
+
+    #[test]
+    fn test_prune_budget_over_hard_limit() {
+        let mut conn_opts = ConnectionOptions::default();
+        conn_opts.num_neighbors = 4;
+        conn_opts.prune.soft_num_neighbors = 2;
+        conn_opts.prune.max_prune_per_cycle = 1;
+
+        // all in one org, so none is ever spared as its org's last peer
+        let mut sim = PeerNetworkPruneSim::new(conn_opts);
+        for i in 0..6 {
+            let addrbytes = PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x08,(i + 1) as u8]);
+            sim.add_peer(addrbytes, 20001, 1, true, 1000);
+        }
+
+        // over the hard limit, the budget gives way until we're back down to it
+        assert_eq!(sim.prune().len(), 2);
+        assert_eq!(sim.prune().len(), 1);
+        assert_eq!(sim.prune().len(), 1);
+        assert_eq!(sim.prune().len(), 0);
+    }
+
+    #[test]
+    fn test_org_distribution_summary() {
+        let mut conn_opts = ConnectionOptions::default();
+        conn_opts.prune.soft_num_neighbors = 5;
+        conn_opts.prune.soft_max_neighbors_per_org = 2;
+
+        let mut sim = PeerNetworkPruneSim::new(conn_opts);
+
+        // org 1 has one peer too many, and pruning it is enough
+        let mut outbound = vec![];
+        for (i, org) in [1, 1, 1, 2, 3, 3].iter().enumerate() {
+            let addrbytes = PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x06,(i + 1) as u8]);
+            outbound.push(sim.add_peer(addrbytes, 20001, *org, true, 1000));
+        }
+        sim.add_peer(PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x06,0x10]), 40001, 4, false, 1000);
+
+        assert_eq!(sim.network.org_distribution_summary(), vec![(1, 3), (3, 2), (2, 1)]);
+
+        let pruned = sim.prune();
+        assert_eq!(pruned.len(), 1);
+
+        let mut expected : HashMap<u32, usize> = HashMap::new();
+        for nk in outbound.iter() {
+            if let Some(inputs) = sim.network.neighbor_prune_inputs(nk) {
+                let count = expected.entry(inputs.org.unwrap()).or_insert(0);
+                *count += 1;
+            }
+        }
+
+        let summary = sim.network.org_distribution_summary();
+        assert_eq!(summary.len(), expected.len());
+        for (org, count) in summary.iter() {
+            assert_eq!(expected.get(org), Some(count));
+        }
+        assert_eq!(summary, vec![(1, 2), (3, 2), (2, 1)]);
+    }
+
+    #[test]
+    fn test_rebalance_swaps_marginal_peer() {
+        let mut conn_opts = ConnectionOptions::default();
+        conn_opts.prune.soft_num_neighbors = 3;
+        conn_opts.prune.rebalance_interval = 60;
+
+        let mut sim = PeerNetworkPruneSim::new(conn_opts);
+
+        // all in org 1
+        let healthy_1 = sim.add_peer(PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x02,0x01]), 20001, 1, true, 1000);
+        let unhealthy = sim.add_peer(PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x02,0x02]), 20001, 1, true, 1000);
+
+        // below capacity, so nothing is evaluated and the rate limit isn't consumed
+        assert_eq!(sim.network.rebalance_frontier(&HashSet::new()).unwrap(), None);
+        assert_eq!(sim.network.rebalance_deadline, 0);
+
+        let healthy_2 = sim.add_peer(PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x02,0x03]), 20001, 1, true, 1000);
+        sim.set_health_score(&healthy_1, 0.9);
+        sim.set_health_score(&unhealthy, 0.1);
+        sim.set_health_score(&healthy_2, 0.9);
+
+        // nothing to prune
+        assert_eq!(sim.prune(), vec![]);
+
+        // but there's a peer in an org we're not connected to
+        sim.add_frontier_peer(PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x03,0x01]), 20001, 2);
+
+        assert_eq!(sim.network.rebalance_frontier(&HashSet::new()).unwrap(), Some(unhealthy.clone()));
+        assert!(!sim.is_connected(&unhealthy));
+        assert!(sim.is_connected(&healthy_1));
+        assert!(sim.is_connected(&healthy_2));
+        assert_eq!(sim.network.prune_outbound_counts.get(&unhealthy), Some(&1));
+
+        // rate-limited, even once we're back at capacity
+        sim.add_peer(PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x02,0x04]), 20001, 1, true, 10);
+        assert_eq!(sim.network.rebalance_frontier(&HashSet::new()).unwrap(), None);
+        assert_eq!(PeerNetwork::count_outbound_conversations(&sim.network.peers), 3);
+    }
+
+    #[test]
+    fn test_rebalance_without_better_candidate() {
+        let mut conn_opts = ConnectionOptions::default();
+        conn_opts.prune.soft_num_neighbors = 3;
+        conn_opts.prune.rebalance_interval = 60;
+
+        let mut sim = PeerNetworkPruneSim::new(conn_opts);
+
+        // two in org 1, one in org 2
+        let unhealthy = sim.add_peer(PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x02,0x01]), 20001, 1, true, 1000);
+        let healthy = sim.add_peer(PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x02,0x02]), 20001, 1, true, 1000);
+        let other_org = sim.add_peer(PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x03,0x01]), 20001, 2, true, 1000);
+        sim.set_health_score(&unhealthy, 0.1);
+        sim.set_health_score(&healthy, 0.9);
+
+        // another org 2 peer would just make org 2 as represented as org 1 is now
+        sim.add_frontier_peer(PeerAddress([0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0x00,0xff,0xff,0x0a,0x00,0x03,0x02]), 20001, 2);
+
+        assert_eq!(sim.network.rebalance_frontier(&HashSet::new()).unwrap(), None);
+        assert!(sim.is_connected(&unhealthy));
+        assert!(sim.is_connected(&healthy));
+        assert!(sim.is_connected(&other_org));
+    }
+}
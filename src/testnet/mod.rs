@@ -26,6 +26,7 @@ pub struct NodeConfig {
     pub name: String,
     pub path: String,
     pub mem_pool_path: String,
+    pub check_integrity_on_open: bool,
 }
 
 #[cfg(test)]
@@ -76,7 +76,7 @@ impl Node {
         let seed = Sha256Sum::from_data(format!("{}", config.name).as_bytes());
         let keychain = Keychain::default(seed.as_bytes().to_vec());
 
-        let chain_state = match StacksChainState::open_and_exec(false, TESTNET_CHAIN_ID, &config.path, boot_block_exec) {
+        let chain_state = match StacksChainState::open_and_exec_with_integrity_check(false, TESTNET_CHAIN_ID, &config.path, config.check_integrity_on_open, boot_block_exec) {
             Ok(res) => res,
             Err(_) => panic!("Error while opening chain state at path {:?}", config.path)
         };
@@ -1,15 +1,130 @@
 use std::collections::{HashMap, BTreeMap, BTreeSet};
 
-use vm::types::{TypeSignature, FunctionType, QualifiedContractIdentifier, TraitIdentifier};
-use vm::types::signatures::FunctionSignature;
+use vm::types::{TypeSignature, FunctionType, QualifiedContractIdentifier, StandardPrincipalData, TraitIdentifier};
+use vm::types::signatures::{FunctionSignature, FixedFunction};
 use vm::database::{ClaritySerializable, ClarityDeserializable,
-                   RollbackWrapper, MarfedKV, ClarityBackingStore};
+                   RollbackWrapper, MarfedKV, ClarityBackingStore, CheckpointMode};
 use vm::analysis::errors::{CheckError, CheckErrors, CheckResult};
+use vm::errors::{Error as ClarityError};
 use vm::analysis::type_checker::{ContractAnalysis};
 use vm::representations::{ClarityName};
+use util::hash::Sha512Trunc256Sum;
+
+// bump this whenever the export format or the serialized ContractAnalysis changes incompatibly
+pub const ANALYSIS_EXPORT_VERSION: u32 = 1;
 
 pub struct AnalysisDatabase <'a> {
-    store: RollbackWrapper <'a>
+    store: RollbackWrapper <'a>,
+    limits: AnalysisDbLimits,
+    // running totals checked against the limits. None until the limits are first checked, since
+    //   counting means loading every stored analysis.
+    usage: Option<AnalysisDbUsage>,
+    // the totals as of each open nested context, restored if it's rolled back
+    usage_checkpoints: Vec<Option<AnalysisDbUsage>>
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct AnalysisDbUsage {
+    num_contracts: u64,
+    analysis_bytes: u64
+}
+
+// caps on how much the analysis database may hold, so a flood of deployments can't bloat it.
+//   None means unlimited.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct AnalysisDbLimits {
+    pub max_contracts: Option<u64>,
+    // total length of the serialized analyses
+    pub max_analysis_bytes: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnalysisDbStats {
+    pub num_contracts: u64,
+    // total length of the serialized analyses
+    pub analysis_bytes: u64,
+    // page count * page size of the side store. None if SQLite couldn't report it.
+    pub total_db_bytes: Option<u64>,
+}
+
+// how a new analysis of a contract differs from the stored one, for reviewing an upgrade.
+//   functions are the public and read-only ones, since only those can be called from outside.
+//   all names are sorted.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct AnalysisDiff {
+    pub added_functions: Vec<String>,
+    pub removed_functions: Vec<String>,
+    // same name, but a different signature or a switch between public and read-only
+    pub changed_functions: Vec<String>,
+    pub added_maps: Vec<String>,
+    pub removed_maps: Vec<String>,
+    pub changed_maps: Vec<String>,
+    // true if anything that callers of the stored contract rely on was removed or changed
+    pub breaking: bool
+}
+
+impl AnalysisDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added_functions.is_empty() && self.removed_functions.is_empty() && self.changed_functions.is_empty() &&
+            self.added_maps.is_empty() && self.removed_maps.is_empty() && self.changed_maps.is_empty()
+    }
+}
+
+// how the stored analyses differ from the deployed contracts, e.g. after a partial rollback.
+//   contracts are identified by their string form, and both lists are sorted.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ReconcileReport {
+    // analyses with no deployed contract
+    pub orphans: Vec<String>,
+    // deployed contracts with no analysis
+    pub gaps: Vec<String>
+}
+
+impl ReconcileReport {
+    pub fn is_consistent(&self) -> bool {
+        self.orphans.is_empty() && self.gaps.is_empty()
+    }
+}
+
+// diffs two name -> value maps into (added, removed, changed) names
+fn diff_maps<V: PartialEq>(old: &BTreeMap<&ClarityName, V>, new: &BTreeMap<&ClarityName, V>) -> (Vec<String>, Vec<String>, Vec<String>) {
+    let added = new.keys()
+        .filter(|name| !old.contains_key(*name))
+        .map(|name| name.to_string())
+        .collect();
+    let removed = old.keys()
+        .filter(|name| !new.contains_key(*name))
+        .map(|name| name.to_string())
+        .collect();
+    let changed = old.iter()
+        .filter(|(name, old_value)| match new.get(*name) {
+            Some(new_value) => new_value != *old_value,
+            None => false
+        })
+        .map(|(name, _)| name.to_string())
+        .collect();
+    (added, removed, changed)
+}
+
+// the public and read-only functions of a contract, with whether each is read-only
+fn callable_functions(contract: &ContractAnalysis) -> BTreeMap<&ClarityName, (bool, &FunctionType)> {
+    let mut functions = BTreeMap::new();
+    for (name, function_type) in contract.public_function_types.iter() {
+        functions.insert(name, (false, function_type));
+    }
+    for (name, function_type) in contract.read_only_function_types.iter() {
+        functions.insert(name, (true, function_type));
+    }
+    functions
+}
+
+// a contract analysis, as shared with another node
+#[derive(Debug, Serialize, Deserialize)]
+struct AnalysisExport {
+    version: u32,
+    // hex hash of the contract source, if this node has it committed
+    source_hash: Option<String>,
+    analysis: ContractAnalysis
 }
 
 impl ClaritySerializable for ContractAnalysis {
@@ -29,10 +144,21 @@ impl ClarityDeserializable<ContractAnalysis> for ContractAnalysis {
 impl <'a> AnalysisDatabase <'a> {
     pub fn new(store: &'a mut dyn ClarityBackingStore) -> AnalysisDatabase<'a> {
         AnalysisDatabase {
-            store: RollbackWrapper::new(store)
+            store: RollbackWrapper::new(store),
+            limits: AnalysisDbLimits::default(),
+            usage: None,
+            usage_checkpoints: vec![]
         }
     }
 
+    pub fn set_limits(&mut self, limits: AnalysisDbLimits) {
+        self.limits = limits;
+    }
+
+    pub fn get_limits(&self) -> &AnalysisDbLimits {
+        &self.limits
+    }
+
     pub fn execute <F, T, E> (&mut self, f: F) -> Result<T,E> where F: FnOnce(&mut Self) -> Result<T,E>, {
         self.begin();
         let result = f(self)
@@ -46,14 +172,19 @@ impl <'a> AnalysisDatabase <'a> {
 
     pub fn begin(&mut self) {
         self.store.nest();
+        self.usage_checkpoints.push(self.usage.clone());
     }
 
     pub fn commit(&mut self) {
         self.store.commit();
+        self.usage_checkpoints.pop();
     }
 
     pub fn roll_back(&mut self) {
         self.store.rollback();
+        if let Some(usage) = self.usage_checkpoints.pop() {
+            self.usage = usage;
+        }
     }
 
     fn storage_key() -> &'static str {
@@ -69,16 +200,166 @@ impl <'a> AnalysisDatabase <'a> {
         self.store.prepare_for_contract_metadata(contract_identifier, Sha512Trunc256Sum([0; 32]));
     }
 
+    // has_contract(), load_contract() and the getters below only read metadata, so they can be
+    //   used outside of begin()/execute() to query committed analyses without opening a
+    //   nested context. Writes still require one.
     pub fn has_contract(&mut self, contract_identifier: &QualifiedContractIdentifier) -> bool {
         self.store.has_metadata_entry(contract_identifier, AnalysisDatabase::storage_key())
     }
 
-    pub fn load_contract(&mut self, contract_identifier: &QualifiedContractIdentifier) -> Option<ContractAnalysis> {
-        self.store.get_metadata(contract_identifier, AnalysisDatabase::storage_key())
-            // treat NoSuchContract error thrown by get_metadata as an Option::None --
+    // returns every contract with a stored analysis, sorted by contract identifier.
+    pub fn list_contracts(&mut self) -> Vec<QualifiedContractIdentifier> {
+        self.store.get_contracts_with_metadata("", AnalysisDatabase::storage_key())
+    }
+
+    // returns the names of the contracts with a stored analysis deployed by `principal`, sorted.
+    pub fn list_contracts_by_principal(&mut self, principal: &StandardPrincipalData) -> CheckResult<Vec<String>> {
+        let contract_prefix = format!("{}.", principal);
+        let contracts = self.store.get_contracts_with_metadata(&contract_prefix, AnalysisDatabase::storage_key());
+        Ok(contracts.into_iter()
+           .filter(|contract| contract.issuer == *principal)
+           .map(|contract| contract.name.to_string())
+           .collect())
+    }
+
+    // counts and sizes of the stored analyses, including pending ones, for capacity planning.
+    //   the side store's size covers everything in it, not only the analyses.
+    pub fn stats(&mut self) -> CheckResult<AnalysisDbStats> {
+        let contracts = self.list_contracts();
+
+        let mut analysis_bytes = 0;
+        for contract_identifier in contracts.iter() {
+            if let Ok(Some(serialized)) = self.store.get_metadata(contract_identifier, AnalysisDatabase::storage_key()) {
+                analysis_bytes += serialized.len() as u64;
+            }
+        }
+
+        let total_db_bytes = match self.store.get_side_store().get_size_bytes() {
+            Ok(bytes) => Some(bytes),
+            Err(e) => {
+                warn!("Failed to get the analysis database size: {:?}", &e);
+                None
+            }
+        };
+
+        Ok(AnalysisDbStats {
+            num_contracts: contracts.len() as u64,
+            analysis_bytes,
+            total_db_bytes
+        })
+    }
+
+    // compares the stored analyses, including pending ones, against the identifiers of the
+    //   deployed contracts.  nothing is added or removed -- it's up to the caller to re-analyze
+    //   gaps or drop orphans.
+    pub fn reconcile(&mut self, deployed: &[String]) -> CheckResult<ReconcileReport> {
+        let stored: BTreeSet<String> = self.list_contracts().iter()
+            .map(|contract_identifier| contract_identifier.to_string())
+            .collect();
+        let deployed: BTreeSet<&String> = deployed.iter().collect();
+
+        Ok(ReconcileReport {
+            orphans: stored.iter()
+                .filter(|contract| !deployed.contains(contract))
+                .cloned()
+                .collect(),
+            gaps: deployed.iter()
+                .filter(|contract| !stored.contains(**contract))
+                .map(|contract| contract.to_string())
+                .collect()
+        })
+    }
+
+    // flushes the side store's write-ahead log into the database file, returning the number of
+    //   frames in the log and the number checkpointed (both -1 if the side store isn't in WAL
+    //   mode).  Meant to be called between analysis batches: it fails if a nested context or
+    //   a block's save point is open, since their writes aren't durable yet anyways.
+    pub fn checkpoint(&mut self, mode: CheckpointMode) -> CheckResult<(i32, i32)> {
+        if self.store.depth() > 0 || self.store.get_side_store().has_open_savepoint() {
+            return Err(CheckErrors::AnalysisCheckpointInTransaction.into())
+        }
+
+        self.store.get_side_store().checkpoint(mode)
+            .map_err(|e| CheckErrors::AnalysisCheckpointFailed(format!("{:?}", &e)).into())
+    }
+
+    // used by tests to store an arbitrary (possibly malformed) analysis blob for a contract.
+    #[cfg(test)]
+    pub fn test_insert_raw_analysis(&mut self, contract_identifier: &QualifiedContractIdentifier, blob: &str) {
+        self.store.insert_metadata(contract_identifier, AnalysisDatabase::storage_key(), blob);
+        self.usage = None;
+    }
+
+    // the stored analysis row, or None if there isn't one.  Only a genuinely absent row is None:
+    //   any other failure to read it is a CorruptAnalysis, so callers don't mistake it for a
+    //   contract that was never analyzed.
+    fn load_contract_row(&mut self, contract_identifier: &QualifiedContractIdentifier) -> CheckResult<Option<String>> {
+        match self.store.get_metadata(contract_identifier, AnalysisDatabase::storage_key()) {
+            Ok(row) => Ok(row),
+            // get_metadata throws NoSuchContract if the contract itself doesn't exist --
             //    the analysis will propagate that as a CheckError anyways.
-            .ok()?
-            .map(|x| ContractAnalysis::deserialize(&x))
+            Err(ClarityError::Unchecked(CheckErrors::NoSuchContract(_))) => Ok(None),
+            Err(e) => {
+                warn!("Failed to read the analysis of {}: {:?}", contract_identifier, &e);
+                Err(CheckErrors::CorruptAnalysis(contract_identifier.to_string()).into())
+            }
+        }
+    }
+
+    pub fn load_contract(&mut self, contract_identifier: &QualifiedContractIdentifier) -> CheckResult<Option<ContractAnalysis>> {
+        let serialized = match self.load_contract_row(contract_identifier)? {
+            None => return Ok(None),
+            Some(x) => x
+        };
+
+        // a bad row shouldn't panic the checker
+        serde_json::from_str(&serialized)
+            .map(|contract| Some(contract))
+            .map_err(|_| CheckErrors::CorruptAnalysis(contract_identifier.to_string()).into())
+    }
+
+    // serializes a stored analysis so another node can import_analysis() it instead of
+    //   re-analyzing the contract.
+    pub fn export_analysis(&mut self, contract_identifier: &QualifiedContractIdentifier) -> CheckResult<Vec<u8>> {
+        let analysis = self.load_contract(contract_identifier)?
+            .ok_or(CheckErrors::NoSuchContract(contract_identifier.to_string()))?;
+        let source_hash = self.store.get_contract_hash(contract_identifier)
+            .ok()
+            .map(|contract_hash| contract_hash.to_hex());
+
+        let export = AnalysisExport {
+            version: ANALYSIS_EXPORT_VERSION,
+            source_hash,
+            analysis
+        };
+        serde_json::to_vec(&export)
+            .map_err(|_| CheckErrors::CorruptAnalysis(contract_identifier.to_string()).into())
+    }
+
+    // inserts an analysis produced by export_analysis().  If source_hash is given, the export
+    //   must have been made from a contract with that source.
+    pub fn import_analysis(&mut self, contract_identifier: &QualifiedContractIdentifier, bytes: &[u8], source_hash: Option<&Sha512Trunc256Sum>) -> CheckResult<()> {
+        // check the version before anything else, since other versions may not parse
+        let version = serde_json::from_slice::<serde_json::Value>(bytes).ok()
+            .and_then(|value| value.get("version").and_then(|version| version.as_u64()))
+            .ok_or(CheckErrors::CorruptAnalysis(contract_identifier.to_string()))?;
+        if version != ANALYSIS_EXPORT_VERSION as u64 {
+            return Err(CheckErrors::AnalysisExportVersion(version as u32, ANALYSIS_EXPORT_VERSION).into())
+        }
+
+        let export: AnalysisExport = serde_json::from_slice(bytes)
+            .map_err(|_| CheckErrors::CorruptAnalysis(contract_identifier.to_string()))?;
+
+        if export.analysis.contract_identifier != *contract_identifier {
+            return Err(CheckErrors::AnalysisExportMismatch(contract_identifier.to_string()).into())
+        }
+        if let Some(expected) = source_hash {
+            if export.source_hash != Some(expected.to_hex()) {
+                return Err(CheckErrors::AnalysisExportMismatch(contract_identifier.to_string()).into())
+            }
+        }
+
+        self.execute(|db| db.insert_contract(contract_identifier, &export.analysis))
     }
 
     pub fn insert_contract(&mut self, contract_identifier: &QualifiedContractIdentifier, contract: &ContractAnalysis) -> CheckResult<()> {
@@ -87,27 +368,90 @@ impl <'a> AnalysisDatabase <'a> {
             return Err(CheckErrors::ContractAlreadyExists(contract_identifier.to_string()).into())
         }
 
-        self.store.insert_metadata(contract_identifier, key, &contract.serialize());
+        let serialized = contract.serialize();
+        self.check_limits(serialized.len() as u64)?;
+
+        self.store.insert_metadata(contract_identifier, key, &serialized);
+        if let Some(ref mut usage) = self.usage {
+            usage.num_contracts += 1;
+            usage.analysis_bytes += serialized.len() as u64;
+        }
         Ok(())
     }
 
+    // would adding an analysis of analysis_len bytes exceed the limits?
+    //   the first check counts the stored analyses; later ones use the running totals.
+    fn check_limits(&mut self, analysis_len: u64) -> CheckResult<()> {
+        if self.limits.max_contracts.is_none() && self.limits.max_analysis_bytes.is_none() {
+            return Ok(())
+        }
+
+        let usage = match self.usage {
+            Some(ref usage) => usage.clone(),
+            None => {
+                let stats = self.stats()?;
+                let usage = AnalysisDbUsage {
+                    num_contracts: stats.num_contracts,
+                    analysis_bytes: stats.analysis_bytes
+                };
+                self.usage = Some(usage.clone());
+                usage
+            }
+        };
+
+        if let Some(max_contracts) = self.limits.max_contracts {
+            if usage.num_contracts + 1 > max_contracts {
+                return Err(CheckErrors::AnalysisDatabaseFull.into())
+            }
+        }
+        if let Some(max_analysis_bytes) = self.limits.max_analysis_bytes {
+            if usage.analysis_bytes + analysis_len > max_analysis_bytes {
+                return Err(CheckErrors::AnalysisDatabaseFull.into())
+            }
+        }
+        Ok(())
+    }
+
+    // inserts a batch of contract analyses in a single nested context.
+    //   if any insert fails (e.g., ContractAlreadyExists), none of the batch is kept.
+    pub fn insert_contracts(&mut self, items: &[(&QualifiedContractIdentifier, &ContractAnalysis)]) -> CheckResult<()> {
+        self.execute(|db| {
+            for (contract_identifier, contract) in items.iter() {
+                db.insert_contract(contract_identifier, contract)?;
+            }
+            Ok(())
+        })
+    }
+
     pub fn get_public_function_type(&mut self, contract_identifier: &QualifiedContractIdentifier, function_name: &str) -> CheckResult<Option<FunctionType>> {
         // TODO: this function loads the whole contract to obtain the function type.
         //         but it doesn't need to -- rather this information can just be 
         //         stored as its own entry. the analysis cost tracking currently only
         //         charges based on the function type size.
-        let contract = self.load_contract(contract_identifier)
+        let contract = self.load_contract(contract_identifier)?
             .ok_or(CheckErrors::NoSuchContract(contract_identifier.to_string()))?;
         Ok(contract.get_public_function_type(function_name)
            .cloned())
     }
 
+    pub fn get_public_function_arg_names(&mut self, contract_identifier: &QualifiedContractIdentifier, function_name: &str) -> CheckResult<Vec<String>> {
+        let function_type = self.get_public_function_type(contract_identifier, function_name)?
+            .ok_or(CheckErrors::NoSuchPublicFunction(contract_identifier.to_string(), function_name.to_string()))?;
+        match function_type {
+            FunctionType::Fixed(FixedFunction { args, .. }) => {
+                Ok(args.into_iter().map(|arg| arg.name.to_string()).collect())
+            },
+            // only native functions have unnamed arguments
+            _ => Ok(vec![])
+        }
+    }
+
     pub fn get_read_only_function_type(&mut self, contract_identifier: &QualifiedContractIdentifier, function_name: &str) -> CheckResult<Option<FunctionType>> {
         // TODO: this function loads the whole contract to obtain the function type.
         //         but it doesn't need to -- rather this information can just be 
         //         stored as its own entry. the analysis cost tracking currently only
         //         charges based on the function type size.
-        let contract = self.load_contract(contract_identifier)
+        let contract = self.load_contract(contract_identifier)?
             .ok_or(CheckErrors::NoSuchContract(contract_identifier.to_string()))?;
         Ok(contract.get_read_only_function_type(function_name)
            .cloned())
@@ -118,24 +462,112 @@ impl <'a> AnalysisDatabase <'a> {
         //         but it doesn't need to -- rather this information can just be 
         //         stored as its own entry. the analysis cost tracking currently only
         //         charges based on the function type size.
-        let contract = self.load_contract(contract_identifier)
+        let contract = self.load_contract(contract_identifier)?
             .ok_or(CheckErrors::NoSuchContract(contract_identifier.to_string()))?;
         Ok(contract.get_defined_trait(trait_name)
            .cloned())
     }
 
     pub fn get_implemented_traits(&mut self, contract_identifier: &QualifiedContractIdentifier) -> CheckResult<BTreeSet<TraitIdentifier>> {
-        let contract = self.load_contract(contract_identifier)
+        let contract = self.load_contract(contract_identifier)?
             .ok_or(CheckErrors::NoSuchContract(contract_identifier.to_string()))?;
         Ok(contract.implemented_traits)
     }
 
+    // does the contract declare (impl-trait ...) for the given trait?  the type checker verified
+    //   any such declaration against the trait when the contract was analyzed, so storage has the
+    //   final word on conformance.
+    pub fn implements_trait(&mut self, contract_identifier: &QualifiedContractIdentifier, trait_contract_identifier: &QualifiedContractIdentifier, trait_name: &str) -> CheckResult<bool> {
+        let trait_contract = self.load_contract(trait_contract_identifier)?
+            .ok_or(CheckErrors::NoSuchContract(trait_contract_identifier.to_string()))?;
+        if trait_contract.get_defined_trait(trait_name).is_none() {
+            return Err(CheckErrors::TraitReferenceUnknown(trait_name.to_string()).into())
+        }
+
+        let implemented_traits = self.get_implemented_traits(contract_identifier)?;
+        Ok(implemented_traits.iter()
+           .any(|trait_identifier| trait_identifier.contract_identifier == *trait_contract_identifier &&
+                trait_identifier.name.as_str() == trait_name))
+    }
+
     pub fn get_map_type(&mut self, contract_identifier: &QualifiedContractIdentifier, map_name: &str) -> CheckResult<(TypeSignature, TypeSignature)> {
-        let contract = self.load_contract(contract_identifier)
+        let contract = self.load_contract(contract_identifier)?
             .ok_or(CheckErrors::NoSuchContract(contract_identifier.to_string()))?;
         let map_type = contract.get_map_type(map_name)
             .ok_or(CheckErrors::NoSuchMap(map_name.to_string()))?;
         Ok(map_type.clone())
     }
 
+    // the names of the maps the contract defines, sorted.
+    pub fn get_map_names(&mut self, contract_identifier: &QualifiedContractIdentifier) -> CheckResult<Vec<String>> {
+        let contract = self.load_contract(contract_identifier)?
+            .ok_or(CheckErrors::NoSuchContract(contract_identifier.to_string()))?;
+        Ok(contract.map_types.keys()
+           .map(|name| name.to_string())
+           .collect())
+    }
+
+    // the names of the public, read-only, and private functions the contract defines, sorted.
+    pub fn get_function_names(&mut self, contract_identifier: &QualifiedContractIdentifier) -> CheckResult<Vec<String>> {
+        let contract = self.load_contract(contract_identifier)?
+            .ok_or(CheckErrors::NoSuchContract(contract_identifier.to_string()))?;
+        let names: BTreeSet<_> = contract.public_function_types.keys()
+            .chain(contract.read_only_function_types.keys())
+            .chain(contract.private_function_types.keys())
+            .map(|name| name.to_string())
+            .collect();
+        Ok(names.into_iter().collect())
+    }
+
+    // the type of (map-get? map-name key): the map's value type wrapped in an optional.
+    pub fn get_map_get_return_type(&mut self, contract_identifier: &QualifiedContractIdentifier, map_name: &str) -> CheckResult<TypeSignature> {
+        let (_, value_type) = self.get_map_type(contract_identifier, map_name)?;
+        Ok(TypeSignature::new_option(value_type)?)
+    }
+
+    // constants are stored alongside the other variable types in the analysis
+    pub fn get_constant_type(&mut self, contract_identifier: &QualifiedContractIdentifier, const_name: &str) -> CheckResult<TypeSignature> {
+        let contract = self.load_contract(contract_identifier)?
+            .ok_or(CheckErrors::NoSuchContract(contract_identifier.to_string()))?;
+        let constant_type = contract.get_variable_type(const_name)
+            .ok_or(CheckErrors::NoSuchConstant(const_name.to_string()))?;
+        Ok(constant_type.clone())
+    }
+
+    // the declared type of a data var (define-data-var).  this is the bare stored type, which is
+    //   also what (var-get ...) returns -- unlike maps, reads aren't wrapped in an optional.
+    pub fn get_persisted_variable_type(&mut self, contract_identifier: &QualifiedContractIdentifier, var_name: &str) -> CheckResult<TypeSignature> {
+        let contract = self.load_contract(contract_identifier)?
+            .ok_or(CheckErrors::NoSuchContract(contract_identifier.to_string()))?;
+        let variable_type = contract.get_persisted_variable_type(var_name)
+            .ok_or(CheckErrors::NoSuchPersistedVariable(contract_identifier.to_string(), var_name.to_string()))?;
+        Ok(variable_type.clone())
+    }
+
+    // compares a new analysis of a contract against the stored one, e.g. before redeploying it.
+    pub fn diff_contract(&mut self, contract_identifier: &QualifiedContractIdentifier, new: &ContractAnalysis) -> CheckResult<AnalysisDiff> {
+        let stored = self.load_contract(contract_identifier)?
+            .ok_or(CheckErrors::NoSuchContract(contract_identifier.to_string()))?;
+
+        let (added_functions, removed_functions, changed_functions) =
+            diff_maps(&callable_functions(&stored), &callable_functions(new));
+
+        let stored_maps: BTreeMap<_, _> = stored.map_types.iter().collect();
+        let new_maps: BTreeMap<_, _> = new.map_types.iter().collect();
+        let (added_maps, removed_maps, changed_maps) = diff_maps(&stored_maps, &new_maps);
+
+        let breaking = !removed_functions.is_empty() || !changed_functions.is_empty() ||
+            !removed_maps.is_empty() || !changed_maps.is_empty();
+
+        Ok(AnalysisDiff {
+            added_functions,
+            removed_functions,
+            changed_functions,
+            added_maps,
+            removed_maps,
+            changed_maps,
+            breaking
+        })
+    }
+
 }
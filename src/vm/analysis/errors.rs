@@ -79,6 +79,7 @@ pub enum CheckErrors {
 
     // variables
     NoSuchDataVariable(String),
+    NoSuchConstant(String),
 
     // data map
     BadMapName,
@@ -97,8 +98,15 @@ pub enum CheckErrors {
     // contract-call errors
     NoSuchContract(String),
     NoSuchPublicFunction(String, String),
+    NoSuchPersistedVariable(String, String),
     ContractAlreadyExists(String),
     ContractCallExpectName,
+    CorruptAnalysis(String),
+    AnalysisExportVersion(u32, u32),
+    AnalysisExportMismatch(String),
+    AnalysisDatabaseFull,
+    AnalysisCheckpointInTransaction,
+    AnalysisCheckpointFailed(String),
 
     // get-block-info? errors
     NoSuchBlockInfoProperty(String),
@@ -308,6 +316,7 @@ impl DiagnosableError for CheckErrors {
             CheckErrors::BadTupleConstruction => format!("invalid tuple syntax, expecting list of pair"),
             CheckErrors::TupleExpectsPairs => format!("invalid tuple syntax, expecting pair"),
             CheckErrors::NoSuchDataVariable(var_name) => format!("use of unresolved persisted variable '{}'", var_name),
+            CheckErrors::NoSuchConstant(const_name) => format!("use of unresolved constant '{}'", const_name),
             CheckErrors::BadTransferSTXArguments => format!("STX transfer expects an int amount, from principal, to principal"),
             CheckErrors::BadTransferFTArguments => format!("transfer expects an int amount, from principal, to principal"),
             CheckErrors::BadTransferNFTArguments => format!("transfer expects an asset, from principal, to principal"),
@@ -322,8 +331,15 @@ impl DiagnosableError for CheckErrors {
             CheckErrors::ReturnTypesMustMatch(type_1, type_2) => format!("detected two execution paths, returning two different expression types (got '{}' and '{}')", type_1, type_2),
             CheckErrors::NoSuchContract(contract_identifier) => format!("use of unresolved contract '{}'", contract_identifier),
             CheckErrors::NoSuchPublicFunction(contract_identifier, function_name) => format!("contract '{}' has no public function '{}'", contract_identifier, function_name),
+            CheckErrors::NoSuchPersistedVariable(contract_identifier, var_name) => format!("contract '{}' has no persisted variable '{}'", contract_identifier, var_name),
             CheckErrors::ContractAlreadyExists(contract_identifier) => format!("contract name '{}' conflicts with existing contract", contract_identifier),
             CheckErrors::ContractCallExpectName => format!("missing contract name for call"),
+            CheckErrors::CorruptAnalysis(contract_identifier) => format!("stored analysis of contract '{}' is corrupt", contract_identifier),
+            CheckErrors::AnalysisExportVersion(found, expected) => format!("unsupported analysis export version {} (expected {})", found, expected),
+            CheckErrors::AnalysisExportMismatch(contract_identifier) => format!("exported analysis does not match contract '{}'", contract_identifier),
+            CheckErrors::AnalysisDatabaseFull => format!("analysis database is full"),
+            CheckErrors::AnalysisCheckpointInTransaction => format!("cannot checkpoint the analysis database while a transaction is open"),
+            CheckErrors::AnalysisCheckpointFailed(reason) => format!("failed to checkpoint the analysis database: {}", reason),
             CheckErrors::NoSuchBlockInfoProperty(property_name) => format!("use of block unknown property '{}'", property_name),
             CheckErrors::GetBlockInfoExpectPropertyName => format!("missing property name for block info introspection"),
             CheckErrors::NameAlreadyUsed(name) => format!("defining '{}' conflicts with previous value", name),
@@ -12,7 +12,7 @@ use vm::types::{TypeSignature, QualifiedContractIdentifier};
 use vm::costs::LimitedCostTracker;
 
 pub use self::errors::{CheckResult, CheckError, CheckErrors};
-pub use self::analysis_db::{AnalysisDatabase};
+pub use self::analysis_db::{AnalysisDatabase, AnalysisDbLimits, AnalysisDiff, ReconcileReport};
 
 use self::read_only_checker::ReadOnlyChecker;
 use self::trait_checker::TraitChecker;
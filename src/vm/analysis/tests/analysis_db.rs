@@ -0,0 +1,928 @@
+use std::env;
+use std::fs;
+use std::convert::TryFrom;
+use rand::Rng;
+use rusqlite::{Connection, NO_PARAMS};
+
+use chainstate::burn::BlockHeaderHash;
+use chainstate::stacks::index::storage::TrieFileStorage;
+use util::hash::{to_hex, Sha512Trunc256Sum};
+use vm::ast::parse;
+use vm::errors::{Error, InterpreterError};
+use vm::database::SqliteConnection;
+use vm::analysis::errors::{CheckErrors, CheckResult};
+use vm::analysis::{AnalysisDatabase, AnalysisDbLimits, ContractAnalysis, ReconcileReport, type_check};
+use vm::database::{MemoryBackingStore, MarfedKV, ClarityBackingStore, CheckpointMode, ClaritySerializable, ClarityDeserializable};
+use vm::types::{QualifiedContractIdentifier, TypeSignature, TupleTypeSignature};
+
+const SIMPLE_CONTRACT: &str =
+    "(define-map balances ((owner principal)) ((amount uint)))
+     (define-public (get-1 (x uint)) (ok u1))
+     (define-read-only (get-balance (owner principal))
+        (default-to u0 (get amount (map-get? balances ((owner owner))))))";
+
+fn analyze(db: &mut AnalysisDatabase, contract_id: &QualifiedContractIdentifier, src: &str) -> ContractAnalysis {
+    let mut contract = parse(contract_id, src).unwrap();
+    type_check(contract_id, &mut contract, db, false).unwrap()
+}
+
+fn make_contract_ids(db: &mut AnalysisDatabase, names: &[&str]) -> Vec<QualifiedContractIdentifier> {
+    let contract_ids: Vec<_> = names.iter()
+        .map(|name| QualifiedContractIdentifier::local(name).unwrap())
+        .collect();
+
+    db.execute(|db| -> CheckResult<()> {
+        for contract_id in contract_ids.iter() {
+            db.test_insert_contract_hash(contract_id);
+        }
+        Ok(())
+    }).unwrap();
+
+    contract_ids
+}
+
+#[test]
+fn test_insert_contracts_batch() {
+    let mut marf = MemoryBackingStore::new();
+    let mut db = marf.as_analysis_db();
+
+    let contract_ids = make_contract_ids(&mut db, &["batch-1", "batch-2", "batch-3"]);
+    let analyses: Vec<_> = contract_ids.iter()
+        .map(|contract_id| analyze(&mut db, contract_id, SIMPLE_CONTRACT))
+        .collect();
+
+    let items: Vec<_> = contract_ids.iter().zip(analyses.iter()).collect();
+    db.insert_contracts(&items).unwrap();
+
+    db.execute(|db| -> CheckResult<()> {
+        for contract_id in contract_ids.iter() {
+            assert!(db.has_contract(contract_id));
+            assert!(db.get_public_function_type(contract_id, "get-1")?.is_some());
+        }
+        Ok(())
+    }).unwrap();
+}
+
+#[test]
+fn test_insert_contracts_batch_is_atomic() {
+    let mut marf = MemoryBackingStore::new();
+    let mut db = marf.as_analysis_db();
+
+    let contract_ids = make_contract_ids(&mut db, &["batch-1", "batch-2"]);
+    let analyses: Vec<_> = contract_ids.iter()
+        .map(|contract_id| analyze(&mut db, contract_id, SIMPLE_CONTRACT))
+        .collect();
+
+    // the last item duplicates the first
+    let items = vec![(&contract_ids[0], &analyses[0]),
+                     (&contract_ids[1], &analyses[1]),
+                     (&contract_ids[0], &analyses[0])];
+
+    let err = db.insert_contracts(&items).unwrap_err();
+    assert_eq!(err.err, CheckErrors::ContractAlreadyExists(contract_ids[0].to_string()));
+
+    db.execute(|db| -> CheckResult<()> {
+        for contract_id in contract_ids.iter() {
+            assert!(!db.has_contract(contract_id));
+        }
+        Ok(())
+    }).unwrap();
+}
+
+#[test]
+fn test_repeated_lookups_across_commit_and_rollback() {
+    let metadata_lookup = "SELECT value FROM metadata_table WHERE blockhash = ? AND key = ?";
+    let mut marf = MemoryBackingStore::new();
+
+    let (contract_id, analysis) = {
+        let mut db = marf.as_analysis_db();
+
+        let contract_ids = make_contract_ids(&mut db, &["lookups"]);
+        let contract_id = contract_ids[0].clone();
+        let analysis = analyze(&mut db, &contract_id, SIMPLE_CONTRACT);
+
+        db.begin();
+        db.insert_contract(&contract_id, &analysis).unwrap();
+        assert!(db.has_contract(&contract_id));
+        db.roll_back();
+
+        db.execute(|db| -> CheckResult<()> {
+            assert!(!db.has_contract(&contract_id));
+            Ok(())
+        }).unwrap();
+
+        db.insert_contracts(&[(&contract_id, &analysis)]).unwrap();
+        (contract_id, analysis)
+    };
+
+    // start from an empty statement cache
+    marf.get_side_store().mut_conn().flush_prepared_statement_cache();
+    SqliteConnection::reset_statement_prepare_counts();
+
+    {
+        let mut db = marf.as_analysis_db();
+        db.execute(|db| -> CheckResult<()> {
+            for _ in 0..10 {
+                assert_eq!(db.load_contract(&contract_id)?.unwrap().serialize(), analysis.serialize());
+                assert!(db.get_public_function_type(&contract_id, "get-1")?.is_some());
+                assert!(db.get_read_only_function_type(&contract_id, "get-balance")?.is_some());
+                assert!(db.get_map_type(&contract_id, "balances").is_ok());
+            }
+            Ok(())
+        }).unwrap();
+    }
+
+    // each kind of statement was prepared once, and every other lookup hit the cache
+    let counts = SqliteConnection::statement_prepare_counts();
+    assert_eq!(counts.get(metadata_lookup), Some(&1));
+    for (sql, count) in counts.iter() {
+        assert_eq!(*count, 1, "'{}' was prepared {} times", sql, count);
+    }
+
+    // releasing or rolling back a save point flushes the cache, so the next lookup re-prepares
+    let side_store = marf.get_side_store();
+    let bhh = BlockHeaderHash([1; 32]);
+
+    side_store.begin(&bhh);
+    assert!(side_store.get_metadata(&bhh, "lookups-hash", "analysis").is_none());
+    assert_eq!(SqliteConnection::statement_prepare_counts().get(metadata_lookup), Some(&1));
+    side_store.commit(&bhh);
+    assert!(side_store.get_metadata(&bhh, "lookups-hash", "analysis").is_none());
+    assert_eq!(SqliteConnection::statement_prepare_counts().get(metadata_lookup), Some(&2));
+
+    side_store.begin(&bhh);
+    side_store.rollback(&bhh);
+    assert!(side_store.get_metadata(&bhh, "lookups-hash", "analysis").is_none());
+    assert!(side_store.get_metadata(&bhh, "lookups-hash", "analysis").is_none());
+    assert_eq!(SqliteConnection::statement_prepare_counts().get(metadata_lookup), Some(&3));
+}
+
+#[test]
+fn test_shared_memory_stores() {
+    let mut writer = MemoryBackingStore::new_shared("test_shared_memory_stores_a");
+    let mut reader = MemoryBackingStore::new_shared("test_shared_memory_stores_a");
+    let mut isolated = MemoryBackingStore::new_shared("test_shared_memory_stores_b");
+
+    let contract_id = {
+        let mut db = writer.as_analysis_db();
+        let contract_ids = make_contract_ids(&mut db, &["shared"]);
+        let analysis = analyze(&mut db, &contract_ids[0], SIMPLE_CONTRACT);
+        db.insert_contracts(&[(&contract_ids[0], &analysis)]).unwrap();
+        contract_ids[0].clone()
+    };
+
+    let mut db = reader.as_analysis_db();
+    db.execute(|db| -> CheckResult<()> {
+        assert!(db.has_contract(&contract_id));
+        assert!(db.get_public_function_type(&contract_id, "get-1")?.is_some());
+        Ok(())
+    }).unwrap();
+
+    let mut db = isolated.as_analysis_db();
+    db.execute(|db| -> CheckResult<()> {
+        assert!(!db.has_contract(&contract_id));
+        Ok(())
+    }).unwrap();
+}
+
+#[test]
+fn test_corrupt_analysis() {
+    let mut marf = MemoryBackingStore::new();
+    let mut db = marf.as_analysis_db();
+
+    let contract_ids = make_contract_ids(&mut db, &["truncated", "mismatched"]);
+    let analysis = analyze(&mut db, &contract_ids[0], SIMPLE_CONTRACT);
+    let serialized = analysis.serialize();
+
+    db.execute(|db| -> CheckResult<()> {
+        db.test_insert_raw_analysis(&contract_ids[0], &serialized[0..serialized.len() / 2]);
+        db.test_insert_raw_analysis(&contract_ids[1], "{\"contract_identifier\": 1}");
+        Ok(())
+    }).unwrap();
+
+    db.execute(|db| -> CheckResult<()> {
+        for contract_id in contract_ids.iter() {
+            let expected = CheckErrors::CorruptAnalysis(contract_id.to_string());
+            assert_eq!(db.load_contract(contract_id).unwrap_err().err, expected);
+            assert_eq!(db.get_public_function_type(contract_id, "get-1").unwrap_err().err, expected);
+            assert_eq!(db.get_map_type(contract_id, "balances").unwrap_err().err, expected);
+        }
+        Ok(())
+    }).unwrap();
+}
+
+#[test]
+fn test_missing_vs_corrupt_analysis() {
+    let mut marf = MemoryBackingStore::new();
+    let mut db = marf.as_analysis_db();
+
+    // "unanalyzed" is deployed, but has no analysis row
+    let contract_ids = make_contract_ids(&mut db, &["unanalyzed", "empty", "truncated"]);
+    let never_deployed = QualifiedContractIdentifier::local("never-deployed").unwrap();
+    let serialized = analyze(&mut db, &contract_ids[2], SIMPLE_CONTRACT).serialize();
+
+    db.execute(|db| -> CheckResult<()> {
+        db.test_insert_raw_analysis(&contract_ids[1], "");
+        db.test_insert_raw_analysis(&contract_ids[2], &serialized[0..serialized.len() / 2]);
+        Ok(())
+    }).unwrap();
+
+    for contract_id in [&never_deployed, &contract_ids[0]].iter() {
+        let expected = CheckErrors::NoSuchContract(contract_id.to_string());
+        assert!(db.load_contract(contract_id).unwrap().is_none());
+        assert_eq!(db.get_public_function_type(contract_id, "get-1").unwrap_err().err, expected);
+        assert_eq!(db.get_read_only_function_type(contract_id, "get-balance").unwrap_err().err, expected);
+        assert_eq!(db.get_map_type(contract_id, "balances").unwrap_err().err, expected);
+    }
+
+    for contract_id in contract_ids[1..].iter() {
+        let expected = CheckErrors::CorruptAnalysis(contract_id.to_string());
+        assert_eq!(db.load_contract(contract_id).unwrap_err().err, expected);
+        assert_eq!(db.get_public_function_type(contract_id, "get-1").unwrap_err().err, expected);
+        assert_eq!(db.get_read_only_function_type(contract_id, "get-balance").unwrap_err().err, expected);
+        assert_eq!(db.get_map_type(contract_id, "balances").unwrap_err().err, expected);
+    }
+}
+
+#[test]
+fn test_getters_without_nested_context() {
+    let mut marf = MemoryBackingStore::new();
+
+    let contract_id = {
+        let mut db = marf.as_analysis_db();
+        let contract_ids = make_contract_ids(&mut db, &["read-only"]);
+        let analysis = analyze(&mut db, &contract_ids[0], SIMPLE_CONTRACT);
+        db.insert_contracts(&[(&contract_ids[0], &analysis)]).unwrap();
+        contract_ids[0].clone()
+    };
+
+    // never calls begin() or execute()
+    let mut db = marf.as_analysis_db();
+    assert!(db.has_contract(&contract_id));
+    assert!(db.get_public_function_type(&contract_id, "get-1").unwrap().is_some());
+    assert!(db.get_public_function_type(&contract_id, "get-2").unwrap().is_none());
+    assert!(db.get_read_only_function_type(&contract_id, "get-balance").unwrap().is_some());
+
+    let missing = QualifiedContractIdentifier::local("missing").unwrap();
+    assert!(!db.has_contract(&missing));
+    assert_eq!(db.get_public_function_type(&missing, "get-1").unwrap_err().err,
+               CheckErrors::NoSuchContract(missing.to_string()));
+}
+
+#[test]
+fn test_get_constant_type() {
+    let mut marf = MemoryBackingStore::new();
+    let mut db = marf.as_analysis_db();
+
+    let contract_ids = make_contract_ids(&mut db, &["constants"]);
+    let contract_id = &contract_ids[0];
+    let analysis = analyze(&mut db, contract_id,
+                           "(define-constant max-supply u1000000)
+                            (define-constant owner 'SZ2J6ZY48GV1EZ5V2V5RB9MP66SW86PYKKQ9H6DPR)
+                            (define-read-only (get-owner) owner)");
+
+    // constant types survive a serialize/deserialize round-trip
+    let deserialized = ContractAnalysis::deserialize(&analysis.serialize());
+    assert_eq!(deserialized.get_variable_type("max-supply"), Some(&TypeSignature::UIntType));
+    assert_eq!(deserialized.get_variable_type("owner"), Some(&TypeSignature::PrincipalType));
+
+    db.insert_contracts(&[(contract_id, &analysis)]).unwrap();
+
+    assert_eq!(db.get_constant_type(contract_id, "max-supply").unwrap(), TypeSignature::UIntType);
+    assert_eq!(db.get_constant_type(contract_id, "owner").unwrap(), TypeSignature::PrincipalType);
+    assert_eq!(db.get_constant_type(contract_id, "min-supply").unwrap_err().err,
+               CheckErrors::NoSuchConstant("min-supply".to_string()));
+
+    let missing = QualifiedContractIdentifier::local("missing").unwrap();
+    assert_eq!(db.get_constant_type(&missing, "max-supply").unwrap_err().err,
+               CheckErrors::NoSuchContract(missing.to_string()));
+}
+
+#[test]
+fn test_get_persisted_variable_type() {
+    let mut marf = MemoryBackingStore::new();
+    let mut db = marf.as_analysis_db();
+
+    let contract_ids = make_contract_ids(&mut db, &["data-vars"]);
+    let contract_id = &contract_ids[0];
+    let analysis = analyze(&mut db, contract_id,
+                           "(define-data-var counter uint u0)
+                            (define-data-var last-caller (optional principal) none)
+                            (define-constant max-count u10)
+                            (define-read-only (get-counter) (var-get counter))");
+
+    // data var types survive a serialize/deserialize round-trip
+    let deserialized = ContractAnalysis::deserialize(&analysis.serialize());
+    assert_eq!(deserialized.persisted_variable_types, analysis.persisted_variable_types);
+    assert_eq!(deserialized.get_persisted_variable_type("counter"), Some(&TypeSignature::UIntType));
+
+    db.insert_contracts(&[(contract_id, &analysis)]).unwrap();
+
+    assert_eq!(db.get_persisted_variable_type(contract_id, "counter").unwrap(), TypeSignature::UIntType);
+    assert_eq!(db.get_persisted_variable_type(contract_id, "last-caller").unwrap(),
+               TypeSignature::new_option(TypeSignature::PrincipalType).unwrap());
+
+    // constants aren't data vars
+    assert_eq!(db.get_persisted_variable_type(contract_id, "max-count").unwrap_err().err,
+               CheckErrors::NoSuchPersistedVariable(contract_id.to_string(), "max-count".to_string()));
+    assert_eq!(db.get_persisted_variable_type(contract_id, "missing").unwrap_err().err,
+               CheckErrors::NoSuchPersistedVariable(contract_id.to_string(), "missing".to_string()));
+
+    let missing = QualifiedContractIdentifier::local("missing").unwrap();
+    assert_eq!(db.get_persisted_variable_type(&missing, "counter").unwrap_err().err,
+               CheckErrors::NoSuchContract(missing.to_string()));
+}
+
+#[test]
+fn test_get_public_function_arg_names() {
+    let mut marf = MemoryBackingStore::new();
+    let mut db = marf.as_analysis_db();
+
+    let contract_ids = make_contract_ids(&mut db, &["arg-names"]);
+    let contract_id = &contract_ids[0];
+    let analysis = analyze(&mut db, contract_id,
+                           "(define-public (transfer (amount uint) (sender principal) (recipient principal)) (ok amount))
+                            (define-public (ping) (ok true))
+                            (define-read-only (get-one (x uint)) u1)");
+    db.insert_contracts(&[(contract_id, &analysis)]).unwrap();
+
+    assert_eq!(db.get_public_function_arg_names(contract_id, "transfer").unwrap(),
+               vec!["amount".to_string(), "sender".to_string(), "recipient".to_string()]);
+    assert_eq!(db.get_public_function_arg_names(contract_id, "ping").unwrap(), Vec::<String>::new());
+
+    // read-only functions aren't public functions
+    assert_eq!(db.get_public_function_arg_names(contract_id, "get-one").unwrap_err().err,
+               CheckErrors::NoSuchPublicFunction(contract_id.to_string(), "get-one".to_string()));
+    assert_eq!(db.get_public_function_arg_names(contract_id, "missing").unwrap_err().err,
+               CheckErrors::NoSuchPublicFunction(contract_id.to_string(), "missing".to_string()));
+
+    let missing = QualifiedContractIdentifier::local("missing").unwrap();
+    assert_eq!(db.get_public_function_arg_names(&missing, "transfer").unwrap_err().err,
+               CheckErrors::NoSuchContract(missing.to_string()));
+}
+
+#[test]
+fn test_get_map_and_function_names() {
+    let mut marf = MemoryBackingStore::new();
+    let mut db = marf.as_analysis_db();
+
+    let contract_ids = make_contract_ids(&mut db, &["names", "no-names"]);
+    let analysis = analyze(&mut db, &contract_ids[0],
+                           "(define-map owners ((id uint)) ((owner principal)))
+                            (define-map balances ((owner principal)) ((amount uint)))
+                            (define-map allowances ((owner principal)) ((amount uint)))
+                            (define-private (check (x uint)) (> x u0))
+                            (define-public (transfer (x uint)) (ok (check x)))
+                            (define-read-only (get-balance) u0)
+                            (define-public (burn) (ok true))");
+    let empty_analysis = analyze(&mut db, &contract_ids[1], "(define-constant max-supply u1000000)");
+    db.insert_contracts(&[(&contract_ids[0], &analysis), (&contract_ids[1], &empty_analysis)]).unwrap();
+
+    assert_eq!(db.get_map_names(&contract_ids[0]).unwrap(),
+               vec!["allowances".to_string(), "balances".to_string(), "owners".to_string()]);
+    assert_eq!(db.get_function_names(&contract_ids[0]).unwrap(),
+               vec!["burn".to_string(), "check".to_string(), "get-balance".to_string(), "transfer".to_string()]);
+
+    assert_eq!(db.get_map_names(&contract_ids[1]).unwrap(), Vec::<String>::new());
+    assert_eq!(db.get_function_names(&contract_ids[1]).unwrap(), Vec::<String>::new());
+
+    let missing = QualifiedContractIdentifier::local("missing").unwrap();
+    assert_eq!(db.get_map_names(&missing).unwrap_err().err,
+               CheckErrors::NoSuchContract(missing.to_string()));
+    assert_eq!(db.get_function_names(&missing).unwrap_err().err,
+               CheckErrors::NoSuchContract(missing.to_string()));
+}
+
+#[test]
+fn test_get_map_get_return_type() {
+    let mut marf = MemoryBackingStore::new();
+    let mut db = marf.as_analysis_db();
+
+    let contract_ids = make_contract_ids(&mut db, &["map-types"]);
+    let contract_id = &contract_ids[0];
+    let analysis = analyze(&mut db, contract_id,
+                           "(define-map balances ((owner principal)) ((amount uint)))
+                            (define-map accounts ((id int)) ((owner principal) (limits (tuple (daily uint) (total uint)))))");
+    db.insert_contracts(&[(contract_id, &analysis)]).unwrap();
+
+    let balance_type = TypeSignature::from(
+        TupleTypeSignature::try_from(vec![("amount".into(), TypeSignature::UIntType)]).unwrap());
+    let limits_type = TypeSignature::from(
+        TupleTypeSignature::try_from(vec![("daily".into(), TypeSignature::UIntType),
+                                          ("total".into(), TypeSignature::UIntType)]).unwrap());
+    let account_type = TypeSignature::from(
+        TupleTypeSignature::try_from(vec![("owner".into(), TypeSignature::PrincipalType),
+                                          ("limits".into(), limits_type)]).unwrap());
+
+    // the raw accessor still gives the declared value type
+    assert_eq!(db.get_map_type(contract_id, "balances").unwrap().1, balance_type);
+
+    assert_eq!(db.get_map_get_return_type(contract_id, "balances").unwrap(),
+               TypeSignature::new_option(balance_type).unwrap());
+    assert_eq!(db.get_map_get_return_type(contract_id, "accounts").unwrap(),
+               TypeSignature::new_option(account_type).unwrap());
+
+    assert_eq!(db.get_map_get_return_type(contract_id, "missing").unwrap_err().err,
+               CheckErrors::NoSuchMap("missing".to_string()));
+}
+
+#[test]
+fn test_list_contracts_ordered() {
+    let mut marf = MemoryBackingStore::new();
+    let mut db = marf.as_analysis_db();
+
+    let names: Vec<String> = (0..40).rev().map(|i| format!("list-{:02}", i)).collect();
+    let name_refs: Vec<&str> = names.iter().map(|name| name.as_str()).collect();
+    let contract_ids = make_contract_ids(&mut db, &name_refs);
+
+    assert!(db.list_contracts().is_empty());
+
+    // insert in several batches, in reverse order
+    let analyses: Vec<_> = contract_ids.iter()
+        .map(|contract_id| analyze(&mut db, contract_id, SIMPLE_CONTRACT))
+        .collect();
+    let items: Vec<_> = contract_ids.iter().zip(analyses.iter()).collect();
+    for batch in items.chunks(7) {
+        db.insert_contracts(batch).unwrap();
+    }
+
+    let mut expected = contract_ids.clone();
+    expected.sort_by_key(|contract_id| contract_id.to_string());
+    assert_eq!(db.list_contracts(), expected);
+
+    // pending analyses are listed until rolled back
+    let pending_ids = make_contract_ids(&mut db, &["list-pending"]);
+    let pending = analyze(&mut db, &pending_ids[0], SIMPLE_CONTRACT);
+    db.begin();
+    db.insert_contract(&pending_ids[0], &pending).unwrap();
+    assert!(db.list_contracts().contains(&pending_ids[0]));
+    assert_eq!(db.list_contracts().len(), expected.len() + 1);
+    db.roll_back();
+
+    assert_eq!(db.list_contracts(), expected);
+}
+
+#[test]
+fn test_list_contracts_by_principal() {
+    let mut marf = MemoryBackingStore::new();
+    let mut db = marf.as_analysis_db();
+
+    let alice = "SZ2J6ZY48GV1EZ5V2V5RB9MP66SW86PYKKQ9H6DPR";
+    let bob = "SM2J6ZY48GV1EZ5V2V5RB9MP66SW86PYKKQVX8X0G";
+    let contract_ids: Vec<_> = [(alice, "tokens"), (bob, "names"), (alice, "exchange"), (bob, "tokens"), (alice, "a-market")].iter()
+        .map(|(principal, name)| QualifiedContractIdentifier::parse(&format!("{}.{}", principal, name)).unwrap())
+        .collect();
+    let alice_principal = contract_ids[0].issuer.clone();
+    let bob_principal = contract_ids[1].issuer.clone();
+
+    db.execute(|db| -> CheckResult<()> {
+        for contract_id in contract_ids.iter() {
+            db.test_insert_contract_hash(contract_id);
+        }
+        Ok(())
+    }).unwrap();
+
+    assert!(db.list_contracts_by_principal(&alice_principal).unwrap().is_empty());
+
+    // bob's "tokens" stays pending until the end
+    for contract_id in contract_ids.iter().take(3) {
+        let analysis = analyze(&mut db, contract_id, SIMPLE_CONTRACT);
+        db.insert_contracts(&[(contract_id, &analysis)]).unwrap();
+    }
+    let analysis = analyze(&mut db, &contract_ids[4], SIMPLE_CONTRACT);
+    db.insert_contracts(&[(&contract_ids[4], &analysis)]).unwrap();
+
+    assert_eq!(db.list_contracts_by_principal(&alice_principal).unwrap(),
+               vec!["a-market".to_string(), "exchange".to_string(), "tokens".to_string()]);
+    assert_eq!(db.list_contracts_by_principal(&bob_principal).unwrap(),
+               vec!["names".to_string()]);
+
+    let pending = analyze(&mut db, &contract_ids[3], SIMPLE_CONTRACT);
+    db.begin();
+    db.insert_contract(&contract_ids[3], &pending).unwrap();
+    assert_eq!(db.list_contracts_by_principal(&bob_principal).unwrap(),
+               vec!["names".to_string(), "tokens".to_string()]);
+    assert_eq!(db.list_contracts_by_principal(&alice_principal).unwrap().len(), 3);
+    db.roll_back();
+
+    assert_eq!(db.list_contracts_by_principal(&bob_principal).unwrap(),
+               vec!["names".to_string()]);
+
+    // contracts with other deployers, like local ones, aren't listed under either
+    let local_ids = make_contract_ids(&mut db, &["tokens"]);
+    let analysis = analyze(&mut db, &local_ids[0], SIMPLE_CONTRACT);
+    db.insert_contracts(&[(&local_ids[0], &analysis)]).unwrap();
+    assert_eq!(db.list_contracts_by_principal(&alice_principal).unwrap().len(), 3);
+    assert_eq!(db.list_contracts_by_principal(&local_ids[0].issuer).unwrap(), vec!["tokens".to_string()]);
+}
+
+#[test]
+fn test_contract_count_limit() {
+    let mut marf = MemoryBackingStore::new();
+    let mut db = marf.as_analysis_db();
+    assert_eq!(db.get_limits(), &AnalysisDbLimits::default());
+
+    db.set_limits(AnalysisDbLimits { max_contracts: Some(3), max_analysis_bytes: None });
+
+    let contract_ids = make_contract_ids(&mut db, &["limit-1", "limit-2", "limit-3", "limit-4"]);
+    let analyses: Vec<_> = contract_ids.iter()
+        .map(|contract_id| analyze(&mut db, contract_id, SIMPLE_CONTRACT))
+        .collect();
+
+    db.insert_contracts(&[(&contract_ids[0], &analyses[0]), (&contract_ids[1], &analyses[1])]).unwrap();
+
+    // a batch that would go over the limit is rejected as a whole
+    assert_eq!(db.insert_contracts(&[(&contract_ids[2], &analyses[2]), (&contract_ids[3], &analyses[3])]).unwrap_err().err,
+               CheckErrors::AnalysisDatabaseFull);
+    assert_eq!(db.list_contracts().len(), 2);
+
+    // filling up to the limit is fine
+    db.insert_contracts(&[(&contract_ids[2], &analyses[2])]).unwrap();
+    assert_eq!(db.insert_contracts(&[(&contract_ids[3], &analyses[3])]).unwrap_err().err,
+               CheckErrors::AnalysisDatabaseFull);
+
+    db.begin();
+    assert_eq!(db.insert_contract(&contract_ids[3], &analyses[3]).unwrap_err().err,
+               CheckErrors::AnalysisDatabaseFull);
+    db.roll_back();
+
+    // and lifting the limit lets more in
+    db.set_limits(AnalysisDbLimits::default());
+    db.insert_contracts(&[(&contract_ids[3], &analyses[3])]).unwrap();
+    assert_eq!(db.list_contracts().len(), 4);
+}
+
+#[test]
+fn test_limits_track_rollbacks() {
+    let mut marf = MemoryBackingStore::new();
+    let mut db = marf.as_analysis_db();
+
+    let contract_ids = make_contract_ids(&mut db, &["track-1", "track-2", "track-3"]);
+    let analyses: Vec<_> = contract_ids.iter()
+        .map(|contract_id| analyze(&mut db, contract_id, SIMPLE_CONTRACT))
+        .collect();
+
+    db.set_limits(AnalysisDbLimits { max_contracts: Some(2), max_analysis_bytes: None });
+    db.insert_contracts(&[(&contract_ids[0], &analyses[0])]).unwrap();
+
+    // a rolled-back insert doesn't count against the limit
+    db.begin();
+    db.insert_contract(&contract_ids[1], &analyses[1]).unwrap();
+    db.roll_back();
+
+    db.insert_contracts(&[(&contract_ids[1], &analyses[1])]).unwrap();
+    assert_eq!(db.insert_contracts(&[(&contract_ids[2], &analyses[2])]).unwrap_err().err,
+               CheckErrors::AnalysisDatabaseFull);
+    assert_eq!(db.list_contracts().len(), 2);
+}
+
+#[test]
+fn test_analysis_bytes_limit() {
+    let mut marf = MemoryBackingStore::new();
+    let mut db = marf.as_analysis_db();
+
+    let contract_ids = make_contract_ids(&mut db, &["bytes-1", "bytes-2", "bytes-3"]);
+    let analyses: Vec<_> = contract_ids.iter()
+        .map(|contract_id| analyze(&mut db, contract_id, SIMPLE_CONTRACT))
+        .collect();
+    let lens: Vec<u64> = analyses.iter().map(|analysis| analysis.serialize().len() as u64).collect();
+
+    // room for exactly two analyses
+    db.set_limits(AnalysisDbLimits { max_contracts: None, max_analysis_bytes: Some(lens[0] + lens[1]) });
+
+    db.insert_contracts(&[(&contract_ids[0], &analyses[0])]).unwrap();
+    db.insert_contracts(&[(&contract_ids[1], &analyses[1])]).unwrap();
+    assert_eq!(db.stats().unwrap().analysis_bytes, lens[0] + lens[1]);
+
+    assert_eq!(db.insert_contracts(&[(&contract_ids[2], &analyses[2])]).unwrap_err().err,
+               CheckErrors::AnalysisDatabaseFull);
+    assert_eq!(db.list_contracts().len(), 2);
+}
+
+#[test]
+fn test_stats() {
+    let mut marf = MemoryBackingStore::new();
+    let mut db = marf.as_analysis_db();
+
+    let empty = db.stats().unwrap();
+    assert_eq!(empty.num_contracts, 0);
+    assert_eq!(empty.analysis_bytes, 0);
+    assert!(empty.total_db_bytes.unwrap() > 0);
+
+    let contract_ids = make_contract_ids(&mut db, &["stats-1", "stats-2", "stats-3"]);
+    let analyses: Vec<_> = contract_ids.iter()
+        .map(|contract_id| analyze(&mut db, contract_id, SIMPLE_CONTRACT))
+        .collect();
+
+    db.insert_contracts(&[(&contract_ids[0], &analyses[0])]).unwrap();
+    let one = db.stats().unwrap();
+    assert_eq!(one.num_contracts, 1);
+    assert_eq!(one.analysis_bytes, analyses[0].serialize().len() as u64);
+
+    db.insert_contracts(&[(&contract_ids[1], &analyses[1]), (&contract_ids[2], &analyses[2])]).unwrap();
+    let three = db.stats().unwrap();
+    assert_eq!(three.num_contracts, 3);
+    assert!(three.analysis_bytes > one.analysis_bytes);
+    assert!(three.total_db_bytes.unwrap() >= one.total_db_bytes.unwrap());
+}
+
+#[test]
+fn test_export_import_analysis() {
+    let exported = {
+        let mut marf = MemoryBackingStore::new();
+        let mut db = marf.as_analysis_db();
+        let contract_ids = make_contract_ids(&mut db, &["shared"]);
+        let analysis = analyze(&mut db, &contract_ids[0], SIMPLE_CONTRACT);
+        db.insert_contracts(&[(&contract_ids[0], &analysis)]).unwrap();
+        db.export_analysis(&contract_ids[0]).unwrap()
+    };
+
+    let mut marf = MemoryBackingStore::new();
+    let mut db = marf.as_analysis_db();
+    let contract_ids = make_contract_ids(&mut db, &["shared", "other"]);
+    let contract_id = &contract_ids[0];
+
+    // test_insert_contract_hash() commits an all-zero source hash
+    let source_hash = Sha512Trunc256Sum([0; 32]);
+    let wrong_hash = Sha512Trunc256Sum([1; 32]);
+
+    assert_eq!(db.import_analysis(contract_id, &exported, Some(&wrong_hash)).unwrap_err().err,
+               CheckErrors::AnalysisExportMismatch(contract_id.to_string()));
+    assert_eq!(db.import_analysis(&contract_ids[1], &exported, None).unwrap_err().err,
+               CheckErrors::AnalysisExportMismatch(contract_ids[1].to_string()));
+
+    let mut future: serde_json::Value = serde_json::from_slice(&exported).unwrap();
+    future["version"] = serde_json::Value::from(2);
+    let future = serde_json::to_vec(&future).unwrap();
+    assert_eq!(db.import_analysis(contract_id, &future, None).unwrap_err().err,
+               CheckErrors::AnalysisExportVersion(2, 1));
+
+    assert_eq!(db.import_analysis(contract_id, b"not an analysis", None).unwrap_err().err,
+               CheckErrors::CorruptAnalysis(contract_id.to_string()));
+
+    assert!(!db.has_contract(contract_id));
+    db.import_analysis(contract_id, &exported, Some(&source_hash)).unwrap();
+
+    assert!(db.get_public_function_type(contract_id, "get-1").unwrap().is_some());
+    assert!(db.get_read_only_function_type(contract_id, "get-balance").unwrap().is_some());
+    assert_eq!(db.export_analysis(contract_id).unwrap(), exported);
+
+    // importing twice is like inserting twice
+    assert_eq!(db.import_analysis(contract_id, &exported, None).unwrap_err().err,
+               CheckErrors::ContractAlreadyExists(contract_id.to_string()));
+}
+
+#[test]
+fn test_metadata_indexes() {
+    let mut side_store = SqliteConnection::memory().unwrap();
+
+    let plan = side_store.explain_contracts_with_metadata();
+    assert!(plan.iter().any(|step| step.contains("sqlite_autoindex_metadata_table_1")), "query plan: {:?}", &plan);
+    assert!(!plan.iter().any(|step| step.contains("TEMP B-TREE")), "query plan: {:?}", &plan);
+
+    let index_count: i64 = side_store.mut_conn().query_row(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type = 'index' AND name = 'metadata_table_blockhash'",
+        NO_PARAMS, |row| row.get(0)).unwrap();
+    assert_eq!(index_count, 1);
+}
+
+fn temp_side_store_path() -> String {
+    let mut path = env::temp_dir();
+    let random_bytes = rand::thread_rng().gen::<[u8; 32]>();
+    path.push(format!("{}.sqlite", to_hex(&random_bytes)));
+    path.to_str().unwrap().to_string()
+}
+
+fn assert_integrity_failure(result: Result<SqliteConnection, Error>) {
+    match result {
+        Err(Error::Interpreter(InterpreterError::SqliteIntegrityFailure(_))) => {},
+        Err(e) => panic!("Expected an integrity failure, got {:?}", e),
+        Ok(_) => panic!("Expected an integrity failure"),
+    }
+}
+
+#[test]
+fn test_side_store_integrity_check() {
+    let analysis = {
+        let mut marf = MemoryBackingStore::new();
+        let mut db = marf.as_analysis_db();
+        let contract_ids = make_contract_ids(&mut db, &["integrity"]);
+        analyze(&mut db, &contract_ids[0], SIMPLE_CONTRACT)
+    };
+    let bhh = BlockHeaderHash([0; 32]);
+
+    // healthy, including a data var named "analysis" whose metadata isn't a contract analysis
+    let healthy_path = temp_side_store_path();
+    {
+        let mut conn = SqliteConnection::initialize(&healthy_path).unwrap();
+        conn.insert_metadata(&bhh, "healthy-hash", "analysis", &analysis.serialize());
+        conn.insert_metadata(&bhh, "healthy-hash", "vm-metadata::9::analysis", "{\"atomic\": \"UIntType\"}");
+    }
+    SqliteConnection::open(&healthy_path, true).unwrap();
+    SqliteConnection::initialize_with_integrity_check(&healthy_path, true).unwrap();
+
+    // unparseable analysis row
+    let bad_row_path = temp_side_store_path();
+    {
+        let mut conn = SqliteConnection::initialize(&bad_row_path).unwrap();
+        conn.insert_metadata(&bhh, "healthy-hash", "analysis", &analysis.serialize());
+        conn.insert_metadata(&bhh, "corrupt-hash", "analysis", "{\"contract_identifier\": ");
+    }
+    assert_integrity_failure(SqliteConnection::open(&bad_row_path, true));
+    assert_integrity_failure(SqliteConnection::initialize_with_integrity_check(&bad_row_path, true));
+    // the check can be skipped
+    SqliteConnection::open(&bad_row_path, false).unwrap();
+    SqliteConnection::initialize(&bad_row_path).unwrap();
+
+    // right table names, wrong columns
+    let bad_schema_path = temp_side_store_path();
+    {
+        let conn = Connection::open(&bad_schema_path).unwrap();
+        conn.execute("CREATE TABLE data_table (key TEXT PRIMARY KEY, value TEXT)", NO_PARAMS).unwrap();
+        conn.execute("CREATE TABLE metadata_table (key TEXT NOT NULL, value TEXT)", NO_PARAMS).unwrap();
+    }
+    assert_integrity_failure(SqliteConnection::open(&bad_schema_path, true));
+    SqliteConnection::open(&bad_schema_path, false).unwrap();
+
+    for path in [healthy_path, bad_row_path, bad_schema_path].iter() {
+        fs::remove_file(path).unwrap();
+    }
+}
+
+#[test]
+fn test_marfed_kv_integrity_check() {
+    let mut path = env::temp_dir();
+    let random_bytes = rand::thread_rng().gen::<[u8; 32]>();
+    path.push(to_hex(&random_bytes));
+    let path_str = path.to_str().unwrap().to_string();
+
+    // a fresh store has nothing to check
+    MarfedKV::open_with_integrity_check(&path_str, None, true).unwrap();
+
+    {
+        let mut marf = MarfedKV::open(&path_str, None).unwrap();
+        marf.get_side_store().insert_metadata(&BlockHeaderHash([0; 32]), "corrupt-hash", "analysis", "{\"contract_identifier\": ");
+    }
+
+    match MarfedKV::open_with_integrity_check(&path_str, None, true) {
+        Err(Error::Interpreter(InterpreterError::SqliteIntegrityFailure(_))) => {},
+        Err(e) => panic!("Expected an integrity failure, got {:?}", e),
+        Ok(_) => panic!("Expected an integrity failure"),
+    }
+    MarfedKV::open(&path_str, None).unwrap();
+
+    fs::remove_dir_all(&path).unwrap();
+}
+
+#[test]
+fn test_checkpoint() {
+    let mut path = env::temp_dir();
+    let random_bytes = rand::thread_rng().gen::<[u8; 32]>();
+    path.push(to_hex(&random_bytes));
+
+    let mut marf = MarfedKV::open(path.to_str().unwrap(), None).unwrap();
+    let journal_mode: String = marf.get_side_store().mut_conn()
+        .query_row("PRAGMA journal_mode=WAL", NO_PARAMS, |row| row.get(0)).unwrap();
+    assert_eq!(journal_mode, "wal");
+
+    let bhh = BlockHeaderHash([1; 32]);
+    marf.begin(&TrieFileStorage::block_sentinel(), &bhh);
+    {
+        let mut db = marf.as_analysis_db();
+        let contract_ids = make_contract_ids(&mut db, &["checkpoint-1", "checkpoint-2"]);
+        let analyses: Vec<_> = contract_ids.iter()
+            .map(|contract_id| analyze(&mut db, contract_id, SIMPLE_CONTRACT))
+            .collect();
+        let items: Vec<_> = contract_ids.iter().zip(analyses.iter()).collect();
+        db.insert_contracts(&items).unwrap();
+
+        // the block's save point is still open
+        assert_eq!(db.checkpoint(CheckpointMode::Passive).unwrap_err().err,
+                   CheckErrors::AnalysisCheckpointInTransaction);
+    }
+    marf.test_commit();
+
+    let mut db = marf.as_analysis_db();
+    let (log_frames, checkpointed_frames) = db.checkpoint(CheckpointMode::Passive).unwrap();
+    assert!(log_frames > 0);
+    assert_eq!(checkpointed_frames, log_frames);
+
+    assert_eq!(db.execute(|db| db.checkpoint(CheckpointMode::Passive)).unwrap_err().err,
+               CheckErrors::AnalysisCheckpointInTransaction);
+
+    // truncating empties the log, and the analyses are still there
+    assert_eq!(db.checkpoint(CheckpointMode::Truncate).unwrap(), (0, 0));
+    assert!(db.get_public_function_type(&QualifiedContractIdentifier::local("checkpoint-1").unwrap(), "get-1").unwrap().is_some());
+}
+
+#[test]
+fn test_diff_contract() {
+    let mut marf = MemoryBackingStore::new();
+    let mut db = marf.as_analysis_db();
+
+    let contract_ids = make_contract_ids(&mut db, &["diff-me", "never-stored"]);
+    let stored = analyze(&mut db, &contract_ids[0], SIMPLE_CONTRACT);
+    db.insert_contracts(&[(&contract_ids[0], &stored)]).unwrap();
+
+    // identical
+    let same = analyze(&mut db, &contract_ids[0], SIMPLE_CONTRACT);
+    let diff = db.diff_contract(&contract_ids[0], &same).unwrap();
+    assert!(diff.is_empty());
+    assert!(!diff.breaking);
+
+    // an added function isn't breaking
+    let added_src = format!("{}\n(define-public (get-2 (x uint)) (ok u2))", SIMPLE_CONTRACT);
+    let added = analyze(&mut db, &contract_ids[0], &added_src);
+    let diff = db.diff_contract(&contract_ids[0], &added).unwrap();
+    assert_eq!(diff.added_functions, vec!["get-2".to_string()]);
+    assert!(diff.removed_functions.is_empty());
+    assert!(diff.changed_functions.is_empty());
+    assert!(!diff.breaking);
+
+    // a changed signature is
+    let changed_src =
+        "(define-map balances ((owner principal)) ((amount uint)))
+         (define-public (get-1 (x int)) (ok u1))
+         (define-read-only (get-balance (owner principal))
+            (default-to u0 (get amount (map-get? balances ((owner owner))))))";
+    let changed = analyze(&mut db, &contract_ids[0], changed_src);
+    let diff = db.diff_contract(&contract_ids[0], &changed).unwrap();
+    assert_eq!(diff.changed_functions, vec!["get-1".to_string()]);
+    assert!(diff.added_functions.is_empty());
+    assert!(diff.changed_maps.is_empty());
+    assert!(diff.breaking);
+
+    // as is a changed map type
+    let changed_map_src = SIMPLE_CONTRACT.replace("((amount uint))", "((amount int))").replace("default-to u0", "default-to 0");
+    let changed_map = analyze(&mut db, &contract_ids[0], &changed_map_src);
+    let diff = db.diff_contract(&contract_ids[0], &changed_map).unwrap();
+    assert_eq!(diff.changed_maps, vec!["balances".to_string()]);
+    assert_eq!(diff.changed_functions, vec!["get-balance".to_string()]);
+    assert!(diff.breaking);
+
+    assert_eq!(db.diff_contract(&contract_ids[1], &same).unwrap_err().err,
+               CheckErrors::NoSuchContract(contract_ids[1].to_string()));
+}
+
+#[test]
+fn test_implements_trait() {
+    let mut marf = MemoryBackingStore::new();
+    let mut db = marf.as_analysis_db();
+
+    let contract_ids = make_contract_ids(&mut db, &["defines-trait", "conforming", "non-conforming", "missing"]);
+
+    let defining = analyze(&mut db, &contract_ids[0],
+        "(define-trait trait-1 ((get-1 (uint) (response uint uint))))");
+    db.insert_contracts(&[(&contract_ids[0], &defining)]).unwrap();
+
+    let conforming = analyze(&mut db, &contract_ids[1],
+        "(impl-trait .defines-trait.trait-1)
+         (define-public (get-1 (x uint)) (ok u1))");
+    // same functions, but never declares the trait
+    let non_conforming = analyze(&mut db, &contract_ids[2],
+        "(define-public (get-1 (x uint)) (ok u1))");
+    db.insert_contracts(&[(&contract_ids[1], &conforming), (&contract_ids[2], &non_conforming)]).unwrap();
+
+    assert!(db.implements_trait(&contract_ids[1], &contract_ids[0], "trait-1").unwrap());
+    assert!(!db.implements_trait(&contract_ids[2], &contract_ids[0], "trait-1").unwrap());
+
+    // the trait contract must be stored, and must define the trait
+    assert_eq!(db.implements_trait(&contract_ids[1], &contract_ids[3], "trait-1").unwrap_err().err,
+               CheckErrors::NoSuchContract(contract_ids[3].to_string()));
+    assert_eq!(db.implements_trait(&contract_ids[1], &contract_ids[0], "trait-2").unwrap_err().err,
+               CheckErrors::TraitReferenceUnknown("trait-2".to_string()));
+}
+
+#[test]
+fn test_reconcile() {
+    let mut marf = MemoryBackingStore::new();
+    let mut db = marf.as_analysis_db();
+
+    let contract_ids = make_contract_ids(&mut db, &["rec-a", "rec-b", "rec-c", "rec-d"]);
+    let analyses: Vec<_> = contract_ids[0..3].iter()
+        .map(|contract_id| analyze(&mut db, contract_id, SIMPLE_CONTRACT))
+        .collect();
+    let items: Vec<_> = contract_ids[0..3].iter().zip(analyses.iter()).collect();
+    db.insert_contracts(&items).unwrap();
+
+    let names: Vec<String> = contract_ids.iter().map(|contract_id| contract_id.to_string()).collect();
+
+    // everything deployed was analyzed
+    let report = db.reconcile(&names[0..3]).unwrap();
+    assert!(report.is_consistent());
+
+    // deployed contracts are a strict subset of the analyzed ones
+    let report = db.reconcile(&[names[2].clone(), names[0].clone()]).unwrap();
+    assert_eq!(report, ReconcileReport { orphans: vec![names[1].clone()], gaps: vec![] });
+
+    // deployed contracts are a strict superset of the analyzed ones
+    let report = db.reconcile(&names).unwrap();
+    assert_eq!(report, ReconcileReport { orphans: vec![], gaps: vec![names[3].clone()] });
+
+    // neither
+    let report = db.reconcile(&[names[3].clone(), names[1].clone()]).unwrap();
+    assert_eq!(report, ReconcileReport { orphans: vec![names[0].clone(), names[2].clone()], gaps: vec![names[3].clone()] });
+
+    // nothing was changed
+    assert_eq!(db.list_contracts(), contract_ids[0..3].to_vec());
+}
@@ -4,6 +4,7 @@ use vm::analysis::errors::CheckErrors;
 use vm::analysis::{ContractAnalysis, type_check};
 
 mod costs;
+mod analysis_db;
 
 #[test]
 fn test_list_types_must_match() {
@@ -33,7 +33,7 @@ impl TraitChecker {
         for trait_identifier in &contract_analysis.implemented_traits {
 
             let trait_name = trait_identifier.name.to_string();
-            let contract_defining_trait = analysis_db.load_contract(&trait_identifier.contract_identifier)
+            let contract_defining_trait = analysis_db.load_contract(&trait_identifier.contract_identifier)?
                 .ok_or(CheckErrors::TraitReferenceUnknown(trait_identifier.name.to_string()))?;
             
             let trait_definition = contract_defining_trait.get_defined_trait(&trait_name)
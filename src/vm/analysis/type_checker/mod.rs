@@ -270,10 +270,10 @@ impl <'a, 'b> TypeChecker <'a, 'b> {
     pub fn type_check_expects(&mut self, expr: &SymbolicExpression, context: &TypingContext, expected_type: &TypeSignature) -> TypeResult {
         match (&expr.expr, expected_type) {
             (LiteralValue(Value::Principal(PrincipalData::Contract(ref contract_identifier))), TypeSignature::TraitReferenceType(trait_identifier)) => {
-                let contract_to_check = self.db.load_contract(&contract_identifier)
+                let contract_to_check = self.db.load_contract(&contract_identifier)?
                     .ok_or(CheckErrors::NoSuchContract(contract_identifier.to_string()))?;
 
-                let contract_defining_trait = self.db.load_contract(&trait_identifier.contract_identifier)
+                let contract_defining_trait = self.db.load_contract(&trait_identifier.contract_identifier)?
                     .ok_or(CheckErrors::NoSuchContract(contract_identifier.to_string()))?;
 
                 let trait_definition = contract_defining_trait.get_defined_trait(&trait_identifier.name)
@@ -1,7 +1,7 @@
-use super::{MarfedKV, ClarityBackingStore};
+use super::{MarfedKV, ClarityBackingStore, SqliteConnection};
 use vm::errors::{ InterpreterResult as Result };
 use chainstate::burn::BlockHeaderHash;
-use std::collections::{HashMap};
+use std::collections::{HashMap, BTreeMap};
 use util::hash::{Sha512Trunc256Sum};
 use vm::types::QualifiedContractIdentifier;
 use std::{cmp::Eq, hash::Hash, clone::Clone};
@@ -53,6 +53,11 @@ impl <'a> RollbackWrapper <'a> {
         }
     }
 
+    // how many nested contexts are open
+    pub fn depth(&self) -> usize {
+        self.stack.len()
+    }
+
     pub fn nest(&mut self) {
         self.stack.push(RollbackContext { edits: Vec::new(),
                                           metadata_edits: Vec::new() });
@@ -159,6 +164,16 @@ impl <'a> RollbackWrapper <'a> {
         self.store.get_current_block_height()
     }
 
+    pub fn get_side_store(&mut self) -> &mut SqliteConnection {
+        self.store.get_side_store()
+    }
+
+    // reads the committed contract hash; pending commitments aren't visible.
+    pub fn get_contract_hash(&mut self, contract: &QualifiedContractIdentifier) -> Result<Sha512Trunc256Sum> {
+        let (_, contract_hash) = self.store.get_contract_hash(contract)?;
+        Ok(contract_hash)
+    }
+
     pub fn get_block_header_hash(&mut self, block_height: u32) -> Option<BlockHeaderHash> {
         self.store.get_block_at_height(block_height)
     }
@@ -180,10 +195,9 @@ impl <'a> RollbackWrapper <'a> {
 
     // Throws a NoSuchContract error if contract doesn't exist,
     //   returns None if there is no such metadata field.
+    // Unlike get(), this may be called on a non-nested context: with no open context,
+    //   there are no pending edits, so this just reads the committed metadata.
     pub fn get_metadata(&mut self, contract: &QualifiedContractIdentifier, key: &str) -> Result<Option<String>> {
-        self.stack.last()
-            .expect("ERROR: Clarity VM attempted GET on non-nested context.");
-
         // This is THEORETICALLY a spurious clone, but it's hard to turn something like
         //  (&A, &B) into &(A, B).
         let metadata_key = (contract.clone(), key.to_string());
@@ -208,6 +222,27 @@ impl <'a> RollbackWrapper <'a> {
         }
     }
 
+    // Returns every contract whose identifier starts with `contract_prefix` and that has a `key`
+    //   metadata entry, including pending ones, sorted by contract identifier.
+    //   Like get_metadata(), this may be called on a non-nested context.
+    pub fn get_contracts_with_metadata(&mut self, contract_prefix: &str, key: &str) -> Vec<QualifiedContractIdentifier> {
+        // keyed by identifier, which both de-duplicates and sorts
+        let mut contracts: BTreeMap<String, QualifiedContractIdentifier> = self.store.get_contracts_with_metadata(contract_prefix, key)
+            .into_iter()
+            .map(|contract| (contract.to_string(), contract))
+            .collect();
+        for (contract, metadata_key) in self.metadata_lookup_map.keys() {
+            if metadata_key != key {
+                continue;
+            }
+            let contract_str = contract.to_string();
+            if contract_str.starts_with(contract_prefix) {
+                contracts.entry(contract_str).or_insert_with(|| contract.clone());
+            }
+        }
+        contracts.into_iter().map(|(_, contract)| contract).collect()
+    }
+
     pub fn has_metadata_entry(&mut self, contract: &QualifiedContractIdentifier, key: &str) -> bool {
         match self.get_metadata(contract, key) {
             Ok(Some(_)) => true,
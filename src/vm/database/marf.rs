@@ -90,6 +90,24 @@ pub trait ClarityBackingStore {
         Ok(self.get_side_store().get_metadata(&bhh, &contract.to_string(), key))
     }
 
+    /// Returns the contracts whose identifiers start with `contract_prefix` and that have a `key`
+    ///   metadata entry visible from the current chain tip, in the side store's order.
+    fn get_contracts_with_metadata(&mut self, contract_prefix: &str, key: &str) -> Vec<QualifiedContractIdentifier> {
+        let candidates = self.get_side_store().get_contracts_with_metadata(contract_prefix, key);
+        let mut contracts = vec![];
+        for candidate in candidates.iter() {
+            let contract = match QualifiedContractIdentifier::parse(candidate) {
+                Ok(contract) => contract,
+                Err(_) => continue
+            };
+            // the side store holds metadata for every fork
+            if let Ok(Some(_)) = self.get_metadata(&contract, key) {
+                contracts.push(contract);
+            }
+        }
+        contracts
+    }
+
     fn put_all_metadata(&mut self, mut items: Vec<((QualifiedContractIdentifier, String), String)>) {
         for ((contract, key), value) in items.drain(..) {
             self.insert_metadata(&contract, &key, &value);
@@ -117,6 +135,12 @@ impl ContractCommitment {
 
 impl MarfedKV {
     pub fn open(path_str: &str, miner_tip: Option<&BlockHeaderHash>) -> Result<MarfedKV> {
+        MarfedKV::open_with_integrity_check(path_str, miner_tip, false)
+    }
+
+    /// Like open(), but if check_integrity is set, run SqliteConnection::integrity_check() on an
+    ///   existing side store before using it.  This reads the whole side store.
+    pub fn open_with_integrity_check(path_str: &str, miner_tip: Option<&BlockHeaderHash>, check_integrity: bool) -> Result<MarfedKV> {
         let mut path = PathBuf::from(path_str);
 
         std::fs::create_dir_all(&path)
@@ -133,7 +157,7 @@ impl MarfedKV {
             .ok_or_else(|| InterpreterError::BadFileName)?
             .to_string();
 
-        let side_store = SqliteConnection::initialize(&data_path)?;
+        let side_store = SqliteConnection::initialize_with_integrity_check(&data_path, check_integrity)?;
         let marf = MARF::from_path(&marf_path, miner_tip)
             .map_err(|err| InterpreterError::MarfFailure(IncomparableError{ err }))?;
 
@@ -332,6 +356,18 @@ impl MemoryBackingStore {
         memory_marf
     }
 
+    /// Backing store over a named, shared in-memory side store.
+    ///   see SqliteConnection::shared_memory for the concurrency caveats.
+    pub fn new_shared(name: &str) -> MemoryBackingStore {
+        let side_store = SqliteConnection::shared_memory(name).unwrap();
+
+        let mut memory_marf = MemoryBackingStore { side_store };
+
+        memory_marf.as_clarity_db().initialize();
+
+        memory_marf
+    }
+
     pub fn as_clarity_db<'a>(&'a mut self) -> ClarityDatabase<'a> {
         ClarityDatabase::new(self, &NULL_HEADER_DB)
     }
@@ -1,37 +1,151 @@
-use rusqlite::{ErrorCode as SqliteErrorCode, Error as SqliteError, Connection, OptionalExtension, NO_PARAMS, Row, Savepoint};
+use rusqlite::{ErrorCode as SqliteErrorCode, Error as SqliteError, Connection, CachedStatement, OpenFlags, OptionalExtension, NO_PARAMS, Row, Savepoint};
 use rusqlite::types::{ToSql, FromSql};
 
+use std::fs;
+#[cfg(test)]
+use std::cell::RefCell;
+#[cfg(test)]
+use std::collections::HashMap;
+
 use chainstate::burn::BlockHeaderHash;
 
 use vm::contracts::Contract;
+use vm::analysis::ContractAnalysis;
 use vm::errors::{Error, InterpreterError, RuntimeErrorType, InterpreterResult as Result, IncomparableError};
 
 const SQL_FAIL_MESSAGE: &str = "PANIC: SQL Failure in Smart Contract VM.";
 
+// how many stored contract analyses integrity_check() tries to parse
+const INTEGRITY_CHECK_SAMPLE_SIZE: u32 = 16;
+
+// samples stored contract analyses, i.e. keys of the form clr-meta::<contract>::analysis.
+//   contract identifiers never contain "::", so this skips the metadata of data vars and maps
+//   named "analysis" (clr-meta::<contract>::vm-metadata::<type>::analysis).
+const INTEGRITY_CHECK_SAMPLE_QUERY: &str = "SELECT key, value FROM metadata_table
+    WHERE key GLOB 'clr-meta::*::analysis' AND instr(substr(key, 11, length(key) - 20), '::') = 0
+    ORDER BY RANDOM() LIMIT ?";
+
+// enumerates the contracts with a given metadata key. The GLOB pattern has a literal prefix, so
+//   SQLite range-scans the (key, blockhash) unique index on metadata_table, which also supplies
+//   the ORDER BY without a sort.
+const CONTRACTS_WITH_METADATA_QUERY: &str = "SELECT DISTINCT key FROM metadata_table WHERE key GLOB ? ORDER BY key";
+
 pub struct SqliteConnection {
     conn: Connection
 }
 
+// how hard wal_checkpoint tries to copy the write-ahead log into the database file.
+//   see https://www.sqlite.org/pragma.html#pragma_wal_checkpoint
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CheckpointMode {
+    // checkpoint as much as possible without waiting on readers or writers
+    Passive,
+    // wait for writers, then checkpoint everything
+    Full,
+    // like Full, and also wait for readers so the log restarts from the beginning
+    Restart,
+    // like Restart, and also truncate the log file to zero bytes
+    Truncate
+}
+
+impl CheckpointMode {
+    fn as_pragma_arg(&self) -> &'static str {
+        match self {
+            CheckpointMode::Passive => "PASSIVE",
+            CheckpointMode::Full => "FULL",
+            CheckpointMode::Restart => "RESTART",
+            CheckpointMode::Truncate => "TRUNCATE"
+        }
+    }
+}
+
+#[cfg(test)]
+thread_local! {
+    // how many times each statement missed the statement cache and had to be prepared
+    static STATEMENT_PREPARES: RefCell<HashMap<String, u64>> = RefCell::new(HashMap::new());
+}
+
+// every cached statement is prepared through here, so tests can count the cache misses
+fn prepare_cached<'a>(conn: &'a Connection, sql: &str) -> rusqlite::Result<CachedStatement<'a>> {
+    #[cfg(test)]
+    count_statement_prepare(conn, sql);
+    conn.prepare_cached(sql)
+}
+
+// a cached statement stays prepared on the connection between uses, so a statement is only
+//   prepared if the connection has no prepared copy of it yet.
+#[cfg(test)]
+fn count_statement_prepare(conn: &Connection, sql: &str) {
+    use rusqlite::ffi;
+    use std::ffi::CStr;
+    use std::ptr;
+
+    let mut is_prepared = false;
+    unsafe {
+        let db = conn.handle();
+        let mut stmt = ffi::sqlite3_next_stmt(db, ptr::null_mut());
+        while !stmt.is_null() {
+            if CStr::from_ptr(ffi::sqlite3_sql(stmt)).to_bytes() == sql.as_bytes() {
+                is_prepared = true;
+                break;
+            }
+            stmt = ffi::sqlite3_next_stmt(db, stmt);
+        }
+    }
+
+    if !is_prepared {
+        STATEMENT_PREPARES.with(|counts| {
+            *counts.borrow_mut().entry(sql.to_string()).or_insert(0) += 1;
+        });
+    }
+}
+
 fn sqlite_put(conn: &Connection, key: &str, value: &str) {
     let params: [&dyn ToSql; 2] = [&key, &value];
-    conn.execute("REPLACE INTO data_table (key, value) VALUES (?, ?)",
-                      &params)
+    prepare_cached(conn, "REPLACE INTO data_table (key, value) VALUES (?, ?)")
+        .and_then(|mut stmt| stmt.execute(&params))
         .expect(SQL_FAIL_MESSAGE);
 }
 
 fn sqlite_get(conn: &Connection, key: &str) -> Option<String> {
     let params: [&dyn ToSql; 1] = [&key];
-    conn.query_row(
-        "SELECT value FROM data_table WHERE key = ?",
-        &params,
-        |row| row.get(0))
-        .optional()
+    prepare_cached(conn, "SELECT value FROM data_table WHERE key = ?")
+        .and_then(|mut stmt| stmt.query_row(&params, |row| row.get(0)).optional())
         .expect(SQL_FAIL_MESSAGE)
 }
+
+// matches `literal` exactly in a GLOB pattern
+fn glob_escape(literal: &str) -> String {
+    let mut escaped = String::with_capacity(literal.len());
+    for c in literal.chars() {
+        match c {
+            '*' | '?' | '[' => {
+                escaped.push('[');
+                escaped.push(c);
+                escaped.push(']');
+            },
+            _ => escaped.push(c)
+        }
+    }
+    escaped
+}
+
 fn sqlite_has_entry(conn: &Connection, key: &str) -> bool {
     sqlite_get(conn, key).is_some()
 }
 
+#[cfg(test)]
+impl SqliteConnection {
+    /// How many times each statement has been prepared on this thread, keyed by its SQL text.
+    pub fn statement_prepare_counts() -> HashMap<String, u64> {
+        STATEMENT_PREPARES.with(|counts| counts.borrow().clone())
+    }
+
+    pub fn reset_statement_prepare_counts() {
+        STATEMENT_PREPARES.with(|counts| counts.borrow_mut().clear());
+    }
+}
+
 impl SqliteConnection {
     pub fn put(&mut self, key: &str, value: &str) {
         sqlite_put(&self.conn, key, value)
@@ -45,7 +159,8 @@ impl SqliteConnection {
         let key = format!("clr-meta::{}::{}", contract_hash, key);
         let params: [&dyn ToSql; 3] = [&bhh, &key, &value.to_string()];
 
-        self.conn.execute("INSERT INTO metadata_table (blockhash, key, value) VALUES (?, ?, ?)", &params)
+        prepare_cached(&self.conn, "INSERT INTO metadata_table (blockhash, key, value) VALUES (?, ?, ?)")
+            .and_then(|mut stmt| stmt.execute(&params))
             .expect(SQL_FAIL_MESSAGE);
     }
 
@@ -69,18 +184,62 @@ impl SqliteConnection {
         let key = format!("clr-meta::{}::{}", contract_hash, key);
         let params: [&dyn ToSql; 2] = [&bhh, &key];
 
-        self.conn.query_row(
-            "SELECT value FROM metadata_table WHERE blockhash = ? AND key = ?",
-            &params,
-            |row| row.get(0))
-            .optional()
+        prepare_cached(&self.conn, "SELECT value FROM metadata_table WHERE blockhash = ? AND key = ?")
+            .and_then(|mut stmt| stmt.query_row(&params, |row| row.get(0)).optional())
             .expect(SQL_FAIL_MESSAGE)
     }
 
+    /// Returns the (unvalidated) identifiers of every contract whose identifier starts with
+    ///   `contract_prefix` and that has a `key` metadata entry in any block, ordered by metadata key.
+    ///   The longer the prefix, the narrower the index range SQLite scans.
+    pub fn get_contracts_with_metadata(&mut self, contract_prefix: &str, key: &str) -> Vec<String> {
+        let prefix = "clr-meta::";
+        let suffix = format!("::{}", key);
+        let pattern = format!("{}{}*{}", prefix, glob_escape(contract_prefix), glob_escape(&suffix));
+        let params: [&dyn ToSql; 1] = [&pattern];
+
+        let mut stmt = prepare_cached(&self.conn, CONTRACTS_WITH_METADATA_QUERY)
+            .expect(SQL_FAIL_MESSAGE);
+        let rows = stmt.query_map(&params, |row| row.get::<_, String>(0))
+            .expect(SQL_FAIL_MESSAGE);
+
+        let contracts = rows.map(|row| {
+            let metadata_key = row.expect(SQL_FAIL_MESSAGE);
+            metadata_key[prefix.len()..(metadata_key.len() - suffix.len())].to_string()
+        }).collect();
+        contracts
+    }
+
     pub fn has_entry(&mut self, key: &str) -> bool {
         sqlite_has_entry(&self.conn, key)
     }
 
+    /// Returns the side store's size in bytes (page count times page size).  For an in-memory
+    ///   side store, this is the memory held by its pages.
+    pub fn get_size_bytes(&mut self) -> Result<u64> {
+        let page_count: i64 = self.conn.query_row("PRAGMA page_count", NO_PARAMS, |row| row.get(0))
+            .map_err(|x| InterpreterError::SqliteError(IncomparableError{ err: x }))?;
+        let page_size: i64 = self.conn.query_row("PRAGMA page_size", NO_PARAMS, |row| row.get(0))
+            .map_err(|x| InterpreterError::SqliteError(IncomparableError{ err: x }))?;
+        Ok((page_count as u64) * (page_size as u64))
+    }
+
+    /// Copies the write-ahead log into the database file, so that a crash doesn't leave a long
+    ///   log to recover.  Returns the number of frames in the log and the number of those that
+    ///   were checkpointed -- both are -1 if the side store isn't in WAL mode.
+    pub fn checkpoint(&mut self, mode: CheckpointMode) -> Result<(i32, i32)> {
+        let sql = format!("PRAGMA wal_checkpoint({})", mode.as_pragma_arg());
+        let (_busy, log_frames, checkpointed_frames): (i32, i32, i32) =
+            self.conn.query_row(&sql, NO_PARAMS, |row| (row.get(0), row.get(1), row.get(2)))
+            .map_err(|x| InterpreterError::SqliteError(IncomparableError{ err: x }))?;
+        Ok((log_frames, checkpointed_frames))
+    }
+
+    /// Is a save point (see begin()) still open?
+    pub fn has_open_savepoint(&self) -> bool {
+        !self.conn.is_autocommit()
+    }
+
     /// begin, commit, rollback a save point identified by key
     ///    this is used to clean up any data from aborted blocks
     ///     (NOT aborted transactions that is handled by the clarity vm directly).
@@ -89,6 +248,8 @@ impl SqliteConnection {
     ///     blockhash would already have committed and no longer exist in the save point stack.
     /// this is a "lower-level" rollback than the roll backs performed in
     ///   ClarityDatabase or AnalysisDatabase -- this is done at the backing store level.
+    /// The lookups above go through the connection's prepared statement cache, which
+    ///   is flushed whenever a save point is released or rolled back.
 
     pub fn begin(&mut self, key: &BlockHeaderHash) {
         self.conn.execute(&format!("SAVEPOINT SP{};", key), NO_PARAMS)
@@ -96,11 +257,13 @@ impl SqliteConnection {
     }
 
     pub fn rollback(&mut self, key: &BlockHeaderHash) {
+        self.conn.flush_prepared_statement_cache();
         self.conn.execute(&format!("ROLLBACK TO SAVEPOINT SP{};", key), NO_PARAMS)
             .expect(SQL_FAIL_MESSAGE);
     }
 
     pub fn commit(&mut self, key: &BlockHeaderHash) {
+        self.conn.flush_prepared_statement_cache();
         self.conn.execute(&format!("RELEASE SAVEPOINT SP{};", key), NO_PARAMS)
             .expect(SQL_FAIL_MESSAGE);
     }
@@ -109,7 +272,19 @@ impl SqliteConnection {
 
 impl SqliteConnection {
     pub fn initialize(filename: &str) -> Result<Self> {
+        Self::initialize_with_integrity_check(filename, false)
+    }
+    /// Like initialize(), but if the side store already exists and check_integrity is set,
+    ///   run integrity_check() on it first.
+    pub fn initialize_with_integrity_check(filename: &str, check_integrity: bool) -> Result<Self> {
+        let exists = filename != ":memory:" && fs::metadata(filename).is_ok();
         let contract_db = Self::inner_open(filename)?;
+        if exists && check_integrity {
+            contract_db.integrity_check()?;
+        }
+        Self::setup_tables(contract_db)
+    }
+    fn setup_tables(contract_db: SqliteConnection) -> Result<Self> {
         contract_db.conn.execute("CREATE TABLE IF NOT EXISTS data_table
                       (key TEXT PRIMARY KEY, value TEXT)", NO_PARAMS)
             .map_err(|x| InterpreterError::SqliteError(IncomparableError{ err: x }))?;
@@ -120,16 +295,52 @@ impl SqliteConnection {
             .map_err(|x| InterpreterError::SqliteError(IncomparableError{ err: x }))?;
 
         contract_db.check_schema()?;
+        contract_db.create_indexes()?;
 
         Ok(contract_db)
     }
+    // also run when opening an existing side store, so that stores created before an
+    //   index was added pick it up.
+    fn create_indexes(&self) -> Result<()> {
+        // commit_metadata_to() and move_metadata_to() select by blockhash
+        self.conn.execute("CREATE INDEX IF NOT EXISTS metadata_table_blockhash
+                      ON metadata_table (blockhash)", NO_PARAMS)
+            .map_err(|x| InterpreterError::SqliteError(IncomparableError{ err: x }))?;
+        Ok(())
+    }
     pub fn memory() -> Result<Self> {
         Self::initialize(":memory:")
     }
-    pub fn open(filename: &str) -> Result<Self> {
+    /// Open an in-memory database that is shared by every connection in this process
+    ///   opened with the same `name`.
+    /// Caveats:
+    ///   - the database only lives as long as at least one connection to it is open.
+    ///   - sharing is per-process; separate processes never see each other's data.
+    ///   - shared-cache mode uses table-level locks, so a connection that tries to read
+    ///     or write while another holds an open save point on the same table fails with
+    ///     SQLITE_LOCKED rather than waiting. Callers must serialize writers themselves.
+    ///   - uncommitted save points are not visible to other connections.
+    pub fn shared_memory(name: &str) -> Result<Self> {
+        let uri = format!("file:{}?mode=memory&cache=shared", name);
+        let flags = OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_CREATE |
+                    OpenFlags::SQLITE_OPEN_URI | OpenFlags::SQLITE_OPEN_SHARED_CACHE;
+        let conn = Connection::open_with_flags(&uri, flags)
+            .map_err(|x| InterpreterError::SqliteError(IncomparableError{ err: x }))?;
+        Self::setup_tables(SqliteConnection { conn })
+    }
+    /// Open an existing side store.  If check_integrity is set, also run integrity_check() --
+    ///   this reads the whole file, so callers that need to open quickly can skip it.
+    pub fn open(filename: &str, check_integrity: bool) -> Result<Self> {
         let contract_db = Self::inner_open(filename)?;
 
         contract_db.check_schema()?;
+        if check_integrity {
+            contract_db.integrity_check()?;
+        }
+        // a missing index only costs speed, so don't refuse to open over it
+        if let Err(e) = contract_db.create_indexes() {
+            warn!("Failed to create side store indexes in {}: {:?}", filename, &e);
+        }
         Ok(contract_db)
     }
     pub fn check_schema(&self) -> Result<()> {
@@ -143,6 +354,76 @@ impl SqliteConnection {
         Ok(())
     }
 
+    /// Check that the side store is actually usable:
+    ///   - SQLite's own PRAGMA integrity_check passes,
+    ///   - the tables have the expected columns, and
+    ///   - a random sample of the stored contract analyses can be parsed.
+    pub fn integrity_check(&self) -> Result<()> {
+        // the pragma returns a single "ok" row, or one row per problem it found
+        let mut problems = vec![];
+        {
+            let mut stmt = self.conn.prepare("PRAGMA integrity_check")
+                .map_err(|x| InterpreterError::SqliteError(IncomparableError{ err: x }))?;
+            let mut rows = stmt.query(NO_PARAMS)
+                .map_err(|x| InterpreterError::SqliteError(IncomparableError{ err: x }))?;
+
+            while let Some(row) = rows.next() {
+                let row = row
+                    .map_err(|x| InterpreterError::SqliteError(IncomparableError{ err: x }))?;
+                let result: String = row.get(0);
+                if result != "ok" {
+                    problems.push(result);
+                }
+            }
+        }
+        if problems.len() > 0 {
+            return Err(InterpreterError::SqliteIntegrityFailure(
+                format!("PRAGMA integrity_check failed: {}", problems.join("; "))).into())
+        }
+
+        self.check_columns("data_table", &["key", "value"])?;
+        self.check_columns("metadata_table", &["key", "blockhash", "value"])?;
+
+        let mut stmt = self.conn.prepare(INTEGRITY_CHECK_SAMPLE_QUERY)
+            .map_err(|x| InterpreterError::SqliteError(IncomparableError{ err: x }))?;
+        let mut rows = stmt.query(&[&INTEGRITY_CHECK_SAMPLE_SIZE])
+            .map_err(|x| InterpreterError::SqliteError(IncomparableError{ err: x }))?;
+
+        while let Some(row) = rows.next() {
+            let row = row
+                .map_err(|x| InterpreterError::SqliteError(IncomparableError{ err: x }))?;
+            let key: String = row.get(0);
+            let value: String = row.get(1);
+            if serde_json::from_str::<ContractAnalysis>(&value).is_err() {
+                return Err(InterpreterError::SqliteIntegrityFailure(
+                    format!("stored analysis '{}' cannot be parsed", key)).into())
+            }
+        }
+
+        Ok(())
+    }
+
+    fn check_columns(&self, table: &str, expected: &[&str]) -> Result<()> {
+        let mut stmt = self.conn.prepare(&format!("PRAGMA table_info({})", table))
+            .map_err(|x| InterpreterError::SqliteError(IncomparableError{ err: x }))?;
+        let mut rows = stmt.query(NO_PARAMS)
+            .map_err(|x| InterpreterError::SqliteError(IncomparableError{ err: x }))?;
+
+        let mut columns = vec![];
+        while let Some(row) = rows.next() {
+            let row = row
+                .map_err(|x| InterpreterError::SqliteError(IncomparableError{ err: x }))?;
+            let column: String = row.get("name");
+            columns.push(column);
+        }
+
+        if columns != expected {
+            return Err(InterpreterError::SqliteIntegrityFailure(
+                format!("table '{}' has columns {:?}, expected {:?}", table, columns, expected)).into())
+        }
+        Ok(())
+    }
+
     pub fn inner_open(filename: &str) -> Result<Self> {
         let conn = Connection::open(filename)
             .map_err(|x| InterpreterError::SqliteError(IncomparableError{ err: x }))?;
@@ -153,4 +434,14 @@ impl SqliteConnection {
     pub fn mut_conn(&mut self) -> &mut Connection {
         &mut self.conn
     }
+
+    #[cfg(test)]
+    pub fn explain_contracts_with_metadata(&mut self) -> Vec<String> {
+        let mut stmt = self.conn.prepare(&format!("EXPLAIN QUERY PLAN {}", CONTRACTS_WITH_METADATA_QUERY))
+            .expect(SQL_FAIL_MESSAGE);
+        let rows = stmt.query_map(&["clr-meta::*::analysis"], |row| row.get::<_, String>(3))
+            .expect(SQL_FAIL_MESSAGE);
+        let plan = rows.map(|row| row.expect(SQL_FAIL_MESSAGE)).collect();
+        plan
+    }
 }
@@ -37,6 +37,7 @@ pub enum InterpreterError {
     UninitializedPersistedVariable,
     FailedToConstructAssetTable,
     SqliteError(IncomparableError<SqliteError>),
+    SqliteIntegrityFailure(String),
     BadFileName,
     FailedToCreateDataDirectory,
     MarfFailure(IncomparableError<MarfError>),